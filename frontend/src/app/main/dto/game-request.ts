@@ -1,5 +1,5 @@
 export interface GameRequest {
     type: "AI" | "User";
     opponent?: String;
-    aiType?: "Random" | "None";
+    aiType?: "Random" | "None" | "AlphaBeta";
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ export interface Game {
     id: number;
     invitation: "Issued" | "Accepted" | "Rejected";
     gameType: "User" | "AI";
-    aiType: "Random" | "None";
+    aiType: "Random" | "None" | "AlphaBeta";
     gameStatus: "NotFinished" | "Won" | "Lost" | "Drawn";
     currentState: Field[][];
     lastMoveRow: number; // TODO
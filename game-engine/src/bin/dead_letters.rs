@@ -0,0 +1,136 @@
+use std::{env, process};
+
+use lapin::{message::Delivery, options::{BasicAckOptions, BasicGetOptions}, BasicProperties, Channel, Connection, ConnectionProperties};
+
+#[path = "../config.rs"]
+#[allow(dead_code)]
+mod config;
+#[path = "../rabbit/topology.rs"]
+#[allow(dead_code)]
+mod topology;
+
+use config::{BrokerConfig, ConfigError, RetryConfig};
+use topology::{declare_topology, get_retry_count, with_retry_count};
+
+enum Command {
+    List,
+    Replay,
+}
+
+const USAGE: &str = "usage: dead_letters <list|replay> [count] [--config <file>] [--<setting> <value>]...";
+
+// takes the same config file, environment and flags as the engine, so it finds the renamed queues
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (config, args) = match config::get_config(&args) {
+        Ok(result) => result,
+        Err(ConfigError::Help) => {
+            println!("{}\n{}", USAGE, config::usage());
+            process::exit(0);
+        },
+        Err(err) => {
+            eprintln!("Invalid configuration: {}", err);
+            process::exit(2);
+        },
+    };
+    let command = match args.first().map(String::as_str) {
+        Some("list") => Command::List,
+        Some("replay") => Command::Replay,
+        _ => usage(),
+    };
+    let limit = match args.get(1).map(|count| count.parse::<usize>()) {
+        None => None,
+        Some(Ok(count)) => Some(count),
+        Some(Err(_)) => usage(),
+    };
+    if args.len() > 2 {
+        usage();
+    }
+
+    let connection = match Connection::connect(&config.rabbit, ConnectionProperties::default()).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Could not connect to RabbitMQ: {}", err);
+            process::exit(1);
+        }
+    };
+    let result = match connection.create_channel().await {
+        Ok(channel) => run(&channel, &config.broker, &config.retry, command, limit).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("RabbitMQ error: {}", err);
+        process::exit(1);
+    }
+    // messages that were only listed are still unacked, so closing the connection puts them back
+    let _ = connection.close(0, "").await;
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+async fn run(channel: &Channel, broker: &BrokerConfig, retry: &RetryConfig, command: Command, limit: Option<usize>) -> Result<(), lapin::Error> {
+    declare_topology(channel, broker, retry).await?;
+
+    let mut count = 0;
+    while limit.is_none_or(|limit| count < limit) {
+        let Some(message) = channel.basic_get(&broker.dead_letter_queue, BasicGetOptions::default()).await? else {
+            break;
+        };
+        let delivery = message.delivery;
+        count += 1;
+        print_message(count, &delivery);
+        if let Command::Replay = command {
+            replay(channel, &broker.exchange, &delivery).await?;
+        }
+    }
+
+    match command {
+        Command::List => println!("{} dead-lettered messages", count),
+        Command::Replay => println!("{} messages replayed to {}", count, broker.exchange),
+    }
+    Ok(())
+}
+
+fn print_message(index: usize, delivery: &Delivery) {
+    println!("#{} routing key: {}, retries: {}, reason: {}",
+             index, delivery.routing_key, get_retry_count(&delivery.properties), get_death_reason(&delivery.properties));
+    println!("{}", String::from_utf8_lossy(&delivery.data));
+}
+
+// the broker records why and from which queue a message was dead-lettered in the x-death header
+fn get_death_reason(properties: &BasicProperties) -> String {
+    let death = properties.headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get("x-death"))
+        .and_then(|deaths| deaths.as_array())
+        .and_then(|deaths| deaths.as_slice().first())
+        .and_then(|death| death.as_field_table());
+    let field = |name: &str| death
+        .and_then(|death| death.inner().get(name))
+        .and_then(|value| value.as_long_string())
+        .map(|value| value.to_string());
+    match (field("reason"), field("queue")) {
+        (Some(reason), Some(queue)) => format!("{} in {}", reason, queue),
+        (Some(reason), None) => reason,
+        _ => String::from("unknown"),
+    }
+}
+
+// replayed messages go back to the moves exchange with a fresh retry budget
+async fn replay(channel: &Channel, exchange: &str, delivery: &Delivery) -> Result<(), lapin::Error> {
+    channel
+        .basic_publish(
+            exchange,
+            delivery.routing_key.as_str(),
+            Default::default(),
+            &delivery.data,
+            with_retry_count(&delivery.properties, 0),
+            )
+        .await?
+        .await?;
+    delivery.acker.ack(BasicAckOptions::default()).await
+}
@@ -0,0 +1,59 @@
+use std::{env, process, time::Instant};
+
+use chess::engine::{generate_bit_board, perft::{perft, perft_divide}, rules::move_to_lan};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// usage: perft <fen|startpos> <depth> [--divide]
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let divide = args.iter().any(|arg| arg == "--divide");
+    let args: Vec<&str> = args.iter().filter(|arg| *arg != "--divide").map(String::as_str).collect();
+    // the FEN may be passed unquoted, so everything before the depth belongs to it
+    let (fen, depth) = match args.split_last() {
+        Some((depth, fen)) if !fen.is_empty() => (fen.join(" "), depth.parse::<usize>()),
+        _ => exit_with_usage(),
+    };
+    let Ok(depth) = depth else {
+        exit_with_usage();
+    };
+    let fen = match fen.as_str() {
+        "startpos" => START_POSITION.to_string(),
+        _ => fen,
+    };
+    let position = match generate_bit_board(&fen) {
+        Ok(position) => position,
+        Err(err) => {
+            eprintln!("Invalid FEN: {}", err);
+            process::exit(1);
+        },
+    };
+
+    let mut board = position.board;
+    let start = Instant::now();
+    let nodes = match (divide, depth) {
+        (true, 1..) => {
+            let mut moves: Vec<(String, u64)> = perft_divide(depth, &mut board, &position.color, &position.enpassant, &position.castling)
+                .iter()
+                .map(|(mov, nodes)| (move_to_lan(mov), *nodes))
+                .collect();
+            moves.sort();
+            for (mov, nodes) in &moves {
+                println!("{}: {}", mov, nodes);
+            }
+            println!();
+            moves.iter().map(|(_, nodes)| nodes).sum()
+        },
+        _ => perft(depth, &mut board, &position.color, &position.enpassant, &position.castling),
+    };
+    let elapsed = start.elapsed();
+
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {} ms", elapsed.as_millis());
+    println!("Nodes per second: {}", (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64);
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("usage: perft <fen|startpos> <depth> [--divide]");
+    process::exit(1);
+}
@@ -0,0 +1,215 @@
+use std::{io::{self, BufRead}, str::FromStr, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc}, thread, time::Duration};
+
+use chess::{engine::{get_engine, position::Position, rules::{move_to_lan, string_to_move}, transposition::{MATE, MATE_THRESHOLD}, get_move_time, EngineType, SearchInfo, SearchLimits}, Color};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const DEFAULT_ENGINE: EngineType = EngineType::AlphaBeta;
+
+enum Command {
+    NewGame(EngineType),
+    // boxed, the position is much larger than the other variants
+    Go(Box<(Position, SearchLimits)>),
+}
+
+fn main() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::spawn(move || search_worker(receiver));
+
+    let mut engine_type = DEFAULT_ENGINE;
+    let mut position = Position::from_fen(START_POSITION).unwrap();
+    let mut history = Vec::new();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name chess");
+                println!("id author xpakx");
+                println!("option name Engine type combo default AlphaBeta var AlphaBeta var Random");
+                println!("uciok");
+            },
+            Some(&"isready") => println!("readyok"),
+            Some(&"setoption") => match parse_option(&tokens[1..]) {
+                Ok(new_type) => {
+                    engine_type = new_type;
+                    sender.send(Command::NewGame(engine_type)).unwrap();
+                },
+                Err(err) => println!("info string {}", err),
+            },
+            Some(&"ucinewgame") => {
+                position = Position::from_fen(START_POSITION).unwrap();
+                history.clear();
+                sender.send(Command::NewGame(engine_type)).unwrap();
+            },
+            Some(&"position") => match parse_position(&tokens[1..]) {
+                Ok((new_position, new_history)) => {
+                    position = new_position;
+                    history = new_history;
+                },
+                Err(err) => println!("info string {}", err),
+            },
+            Some(&"go") => {
+                stop.store(false, Ordering::Relaxed);
+                let mut limits = parse_go(&tokens[1..], &position.color);
+                limits.stop = Some(stop.clone());
+                limits.halfmoves = position.halfmoves;
+                limits.history = history.clone();
+                sender.send(Command::Go(Box::new((position.clone(), limits)))).unwrap();
+            },
+            Some(&"stop") => stop.store(true, Ordering::Relaxed),
+            Some(&"quit") => {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            },
+            _ => {},
+        }
+    }
+
+    drop(sender);
+    worker.join().unwrap();
+}
+
+// engines are created on the worker thread, so they don't need to be Send
+fn search_worker(receiver: Receiver<Command>) {
+    let mut engine = get_engine(DEFAULT_ENGINE);
+    for command in receiver {
+        match command {
+            Command::NewGame(engine_type) => engine = get_engine(engine_type),
+            Command::Go(go) => {
+                let (mut position, limits) = *go;
+                let mov = engine.search(&mut position.board, &position.color, &position.enpassant, &position.castling, &limits, &mut print_info);
+                // in infinite mode the best move can only be sent after stop
+                if let (true, Some(stop)) = (limits.infinite, &limits.stop) {
+                    while !stop.load(Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                }
+                // the null move is sent when the game is already over
+                println!("bestmove {}", mov.map_or(String::from("0000"), |mov| move_to_lan(&mov)));
+            },
+        }
+    }
+}
+
+fn print_info(info: &SearchInfo) {
+    let score = match info.score {
+        score if score > MATE_THRESHOLD => format!("mate {}", (MATE - score + 1) / 2),
+        score if score < -MATE_THRESHOLD => format!("mate -{}", (MATE + score) / 2),
+        score => format!("cp {}", score),
+    };
+    let time = info.time.as_millis() as u64;
+    let nps = info.nodes * 1000 / time.max(1);
+    println!("info depth {} score {} nodes {} nps {} time {} pv {}", info.depth, score, info.nodes, nps, time, move_to_lan(&info.best_move));
+}
+
+fn parse_option(tokens: &[&str]) -> Result<EngineType, String> {
+    match tokens {
+        ["name", "Engine", "value", value] => EngineType::from_str(value),
+        _ => Err(format!("Unknown option: {}", tokens.join(" "))),
+    }
+}
+
+// returns the position with the repetition hashes of the positions before it
+fn parse_position(tokens: &[&str]) -> Result<(Position, Vec<u64>), String> {
+    let moves_index = tokens.iter().position(|token| *token == "moves").unwrap_or(tokens.len());
+    let fen = match tokens.first() {
+        Some(&"startpos") => START_POSITION.to_string(),
+        Some(&"fen") => tokens[1..moves_index].join(" "),
+        _ => return Err("Expected startpos or fen".into()),
+    };
+    let mut position = Position::from_fen(&fen).map_err(|err| err.to_string())?;
+    let mut history = Vec::new();
+
+    for mov in tokens.iter().skip(moves_index + 1) {
+        let mov = string_to_move(&position.board, mov, &position.color, &position.enpassant, &position.castling)
+            .map_err(|err| format!("{}: {}", mov, err))?;
+        history.push(position.repetition_hash());
+        position.make_move(&mov);
+    }
+    Ok((position, history))
+}
+
+fn parse_go(tokens: &[&str], color: &Color) -> SearchLimits {
+    let value = |name: &str| -> Option<u64> {
+        tokens
+            .iter()
+            .position(|token| *token == name)
+            .and_then(|index| tokens.get(index + 1))
+            .and_then(|value| value.parse().ok())
+    };
+
+    let (clock, increment) = match color {
+        Color::White => (value("wtime"), value("winc").unwrap_or(0)),
+        Color::Black => (value("btime"), value("binc").unwrap_or(0)),
+    };
+    let time = match (value("movetime"), clock) {
+        (Some(movetime), _) => Some(movetime),
+        (None, Some(clock)) => Some(get_move_time(clock, increment, value("movestogo"))),
+        (None, None) => None,
+    };
+
+    SearchLimits {
+        depth: value("depth").map(|depth| depth.min(u8::MAX as u64) as u8),
+        time: time.map(Duration::from_millis),
+        infinite: tokens.contains(&"infinite"),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_startpos_with_moves() {
+        let (position, history) = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(position.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert_eq!(history.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_fen_with_castling() {
+        let tokens: Vec<&str> = "fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1 e8c8".split(' ').collect();
+        let (position, _) = parse_position(&tokens).unwrap();
+        assert_eq!(position.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+    }
+
+    #[test]
+    fn test_parse_repeated_position() {
+        let (position, history) = parse_position(&["startpos", "moves", "g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        assert_eq!(history.first(), Some(&position.repetition_hash()));
+    }
+
+    #[test]
+    fn test_parse_illegal_move() {
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_go_depth() {
+        let limits = parse_go(&["depth", "5"], &Color::White);
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.time, None);
+        assert!(!limits.infinite);
+    }
+
+    #[test]
+    fn test_parse_go_clock() {
+        let limits = parse_go(&["wtime", "60000", "btime", "30000", "winc", "1000", "binc", "1000"], &Color::Black);
+        assert_eq!(limits.time, Some(Duration::from_millis(1500)));
+        let limits = parse_go(&["wtime", "60000", "btime", "30000", "movetime", "200"], &Color::White);
+        assert_eq!(limits.time, Some(Duration::from_millis(200)));
+        let limits = parse_go(&["wtime", "40"], &Color::White);
+        assert_eq!(limits.time, Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn test_parse_option() {
+        assert_eq!(parse_option(&["name", "Engine", "value", "Random"]), Ok(EngineType::Random));
+        assert!(parse_option(&["name", "Engine", "value", "Stockfish"]).is_err());
+    }
+}
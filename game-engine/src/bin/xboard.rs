@@ -0,0 +1,343 @@
+use std::{io::{self, BufRead}, str::FromStr, time::Duration};
+
+use chess::{engine::{get_engine, get_move_time, position::{Position, Undo}, rules::{game_state_from_hashes, move_to_lan, string_to_move, GameState, Move, MoveParseError}, transposition::{MATE, MATE_THRESHOLD}, Engine, EngineType, SearchInfo, SearchLimits}, Color};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const DEFAULT_ENGINE: EngineType = EngineType::AlphaBeta;
+// xboard reports mate scores as 100000 plus the number of moves
+const XBOARD_MATE: i32 = 100_000;
+
+struct Level {
+    moves: u64,
+    increment: u64,
+}
+
+struct XBoard {
+    engine: Box<dyn Engine>,
+    engine_type: EngineType,
+    position: Position,
+    // moves with the state needed to take them back and the position before the move
+    history: Vec<(Move, Undo, u64)>,
+    engine_color: Option<Color>,
+    depth: Option<u8>,
+    move_time: Option<u64>,
+    level: Level,
+    clock: Option<u64>,
+    post: bool,
+    finished: bool,
+}
+
+fn main() {
+    let mut xboard = XBoard::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        if !xboard.handle(command, args.trim()) {
+            break;
+        }
+    }
+}
+
+impl XBoard {
+    fn new() -> XBoard {
+        XBoard {
+            engine: get_engine(DEFAULT_ENGINE),
+            engine_type: DEFAULT_ENGINE,
+            position: Position::from_fen(START_POSITION).unwrap(),
+            history: Vec::new(),
+            engine_color: Some(Color::Black),
+            depth: None,
+            move_time: None,
+            level: Level { moves: 0, increment: 0 },
+            clock: None,
+            post: false,
+            finished: false,
+        }
+    }
+
+    // returns false when the engine should quit
+    fn handle(&mut self, command: &str, args: &str) -> bool {
+        match command {
+            "protover" => {
+                println!("feature myname=\"chess\" usermove=1 setboard=1 san=1 ping=1 playother=1 colors=0 analyze=0 sigint=0 sigterm=0 reuse=1");
+                println!("feature option=\"Engine -combo *AlphaBeta /// Random\"");
+                println!("feature done=1");
+            },
+            "new" => {
+                self.engine = get_engine(self.engine_type);
+                self.position = Position::from_fen(START_POSITION).unwrap();
+                self.history.clear();
+                self.engine_color = Some(Color::Black);
+                self.depth = None;
+                self.finished = false;
+            },
+            "force" => self.engine_color = None,
+            "go" => {
+                self.engine_color = Some(self.position.color.clone());
+                self.think();
+            },
+            "playother" => self.engine_color = Some(self.position.color.opposite()),
+            "usermove" => {
+                match self.parse_move(args) {
+                    Ok(mov) => self.make_move(mov),
+                    Err(_) => {
+                        println!("Illegal move: {}", args);
+                        return true;
+                    },
+                }
+                self.think();
+            },
+            "setboard" => match Position::from_fen(args) {
+                Ok(position) => {
+                    self.position = position;
+                    self.history.clear();
+                    self.finished = false;
+                },
+                Err(err) => println!("tellusererror Illegal position: {}", err),
+            },
+            "level" => match parse_level(args) {
+                Some(level) => {
+                    self.level = level;
+                    self.move_time = None;
+                },
+                None => println!("Error (wrong arguments): level {}", args),
+            },
+            "st" => match args.parse::<u64>() {
+                Ok(seconds) => self.move_time = Some(seconds * 1000),
+                Err(_) => println!("Error (wrong arguments): st {}", args),
+            },
+            "sd" => match args.parse::<u8>() {
+                Ok(depth) => self.depth = Some(depth),
+                Err(_) => println!("Error (wrong arguments): sd {}", args),
+            },
+            "time" => self.clock = args.parse::<u64>().ok().map(|centiseconds| centiseconds * 10),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "result" => {
+                self.finished = true;
+                self.engine_color = None;
+            },
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => println!("pong {}", args),
+            "option" => match args.split_once('=') {
+                Some(("Engine", value)) => match EngineType::from_str(value) {
+                    Ok(engine_type) => {
+                        self.engine_type = engine_type;
+                        self.engine = get_engine(engine_type);
+                    },
+                    Err(err) => println!("Error (wrong option): {}", err),
+                },
+                _ => println!("Error (unknown option): {}", args),
+            },
+            "quit" => return false,
+            "xboard" | "accepted" | "rejected" | "otim" | "random" | "hard" | "easy" | "computer" | "name" | "rating" | "" => {},
+            _ => println!("Error (unknown command): {}", command),
+        }
+        true
+    }
+
+    fn parse_move(&self, mov: &str) -> Result<Move, MoveParseError> {
+        let position = &self.position;
+        string_to_move(&position.board, mov, &position.color, &position.enpassant, &position.castling)
+    }
+
+    fn make_move(&mut self, mov: Move) {
+        let hash = self.position.repetition_hash();
+        let undo = self.position.make_move(&mov);
+        self.history.push((mov, undo, hash));
+        self.report_result();
+    }
+
+    // prints the result and returns true when the game is over
+    fn report_result(&mut self) -> bool {
+        let history: Vec<u64> = self.history.iter().map(|(_, _, hash)| *hash).collect();
+        let state = game_state_from_hashes(&self.position, &history);
+        if let Some(result) = get_result(&state, &self.position.color.opposite()) {
+            println!("{}", result);
+            self.finished = true;
+        }
+        self.finished
+    }
+
+    fn take_back(&mut self, moves: usize) {
+        for _ in 0..moves {
+            if let Some((mov, undo, _)) = self.history.pop() {
+                self.position.unmake_move(&mov, undo);
+            }
+        }
+        self.finished = false;
+    }
+
+    fn think(&mut self) {
+        if self.finished || self.engine_color.as_ref() != Some(&self.position.color) {
+            return;
+        }
+        // a position from setboard can already be mate or a draw
+        if self.report_result() {
+            return;
+        }
+        let limits = SearchLimits {
+            depth: self.depth,
+            time: self.get_move_time().map(Duration::from_millis),
+            halfmoves: self.position.halfmoves,
+            history: self.history.iter().map(|(_, _, hash)| *hash).collect(),
+            ..Default::default()
+        };
+        let post = self.post;
+        let position = &mut self.position;
+        let mov = self.engine.search(&mut position.board, &position.color, &position.enpassant, &position.castling, &limits, &mut |info| {
+            if post {
+                print_thinking(info);
+            }
+        });
+        if let Some(mov) = mov {
+            println!("move {}", move_to_lan(&mov));
+            self.make_move(mov);
+        }
+    }
+
+    fn get_move_time(&self) -> Option<u64> {
+        if self.move_time.is_some() {
+            return self.move_time;
+        }
+        let moves_to_go = match self.level.moves {
+            0 => None,
+            moves => Some(moves - (self.history.len() as u64 / 2) % moves),
+        };
+        self.clock.map(|clock| get_move_time(clock, self.level.increment, moves_to_go))
+    }
+}
+
+// color is the side that made the last move
+fn get_result(state: &GameState, color: &Color) -> Option<&'static str> {
+    match (state, color) {
+        (GameState::Checkmate, Color::White) => Some("1-0 {White mates}"),
+        (GameState::Checkmate, Color::Black) => Some("0-1 {Black mates}"),
+        (GameState::Stalemate, _) => Some("1/2-1/2 {Stalemate}"),
+        (GameState::ThreefoldRepetition | GameState::FivefoldRepetition, _) => Some("1/2-1/2 {Draw by repetition}"),
+        (GameState::FiftyMoves | GameState::SeventyFiveMoves, _) => Some("1/2-1/2 {Draw by fifty move rule}"),
+        (GameState::InsufficientMaterial | GameState::DeadPosition, _) => Some("1/2-1/2 {Insufficient material}"),
+        (GameState::Normal | GameState::Check, _) => None,
+    }
+}
+
+// level MPS BASE INC, where base is in minutes or minutes:seconds and increment in seconds
+fn parse_level(args: &str) -> Option<Level> {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let [moves, _base, increment] = args[..] else {
+        return None;
+    };
+    let increment: f64 = increment.parse().ok()?;
+    Some(Level {
+        moves: moves.parse().ok()?,
+        increment: (increment * 1000.0) as u64,
+    })
+}
+
+// ply, score in centipawns, time in centiseconds, nodes and principal variation
+fn print_thinking(info: &SearchInfo) {
+    let score = match info.score {
+        score if score > MATE_THRESHOLD => XBOARD_MATE + (MATE - score + 1) / 2,
+        score if score < -MATE_THRESHOLD => -XBOARD_MATE - (MATE + score) / 2,
+        score => score,
+    };
+    println!("{} {} {} {} {}", info.depth, score, info.time.as_millis() / 10, info.nodes, move_to_lan(&info.best_move));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(xboard: &mut XBoard, line: &str) {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        xboard.handle(command, args);
+    }
+
+    #[test]
+    fn test_usermove_in_force_mode() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "force");
+        send(&mut xboard, "usermove e4");
+        send(&mut xboard, "usermove e7e5");
+        send(&mut xboard, "usermove Nf3");
+        assert_eq!(xboard.position.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+    }
+
+    #[test]
+    fn test_illegal_usermove() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "force");
+        send(&mut xboard, "usermove e5");
+        assert_eq!(xboard.position.to_fen(), START_POSITION);
+        assert!(xboard.history.is_empty());
+    }
+
+    #[test]
+    fn test_engine_replies() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "new");
+        send(&mut xboard, "sd 2");
+        send(&mut xboard, "usermove e4");
+        assert_eq!(xboard.history.len(), 2);
+        assert_eq!(xboard.position.color, Color::White);
+    }
+
+    #[test]
+    fn test_undo_and_remove() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "force");
+        send(&mut xboard, "usermove e4");
+        send(&mut xboard, "usermove e5");
+        send(&mut xboard, "usermove Nf3");
+        send(&mut xboard, "undo");
+        assert_eq!(xboard.position.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        send(&mut xboard, "remove");
+        assert_eq!(xboard.position.to_fen(), START_POSITION);
+    }
+
+    #[test]
+    fn test_setboard_and_mate() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "force");
+        send(&mut xboard, "setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        send(&mut xboard, "sd 2");
+        send(&mut xboard, "go");
+        assert!(xboard.finished);
+        assert_eq!(xboard.position.to_fen(), "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1");
+    }
+
+    #[test]
+    fn test_setboard_finished_game() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "setboard 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        send(&mut xboard, "go");
+        assert!(xboard.finished);
+        assert!(xboard.history.is_empty());
+        assert_eq!(xboard.position.to_fen(), "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+    }
+
+    #[test]
+    fn test_parse_level() {
+        let level = parse_level("40 5 0").unwrap();
+        assert_eq!(level.moves, 40);
+        assert_eq!(level.increment, 0);
+        let level = parse_level("0 2:30 1.5").unwrap();
+        assert_eq!(level.moves, 0);
+        assert_eq!(level.increment, 1500);
+        assert!(parse_level("40 5").is_none());
+    }
+
+    #[test]
+    fn test_move_time() {
+        let mut xboard = XBoard::new();
+        send(&mut xboard, "level 40 5 0");
+        send(&mut xboard, "time 30000");
+        assert_eq!(xboard.get_move_time(), Some(7500));
+        send(&mut xboard, "st 2");
+        assert_eq!(xboard.get_move_time(), Some(2000));
+    }
+}
@@ -0,0 +1,455 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Instant};
+
+use crate::Color;
+
+use super::{position::Position, rules::{get_capture_map, get_legal_moves, Move, Piece}, square::{Bitboard, Square}, transposition::{Bound, TranspositionTable, MATE}, BitBoard, CastlingAvailability, Engine, SearchInfo, SearchLimits};
+
+const INFINITY: i32 = 10_000_000;
+const MAX_DEPTH: u8 = 64;
+// how often the clock and the stop flag are checked
+const NODES_BETWEEN_CHECKS: u64 = 2048;
+
+// piece-square tables from white's perspective, from a8 to h1
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+const KING_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+pub struct AlphaBetaEngine {
+    depth: u8,
+    table: TranspositionTable,
+    nodes: u64,
+    deadline: Option<Instant>,
+    stop: Option<Arc<AtomicBool>>,
+    aborted: bool,
+    // hashes of the game positions and of the positions on the current search path
+    history: Vec<u64>,
+}
+
+impl AlphaBetaEngine {
+    pub fn new(depth: u8, table_size: usize) -> AlphaBetaEngine {
+        AlphaBetaEngine {
+            depth,
+            table: TranspositionTable::new(table_size),
+            nodes: 0,
+            deadline: None,
+            stop: None,
+            aborted: false,
+            history: Vec::new(),
+        }
+    }
+
+    // once the search is aborted every node returns immediately and nothing is stored
+    fn check_abort(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODES_BETWEEN_CHECKS) {
+            let timeout = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            let stopped = self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed));
+            self.aborted = self.aborted || timeout || stopped;
+        }
+        self.aborted
+    }
+
+    // a single repetition is scored as a draw, there is no point in searching it again;
+    // positions before the last capture or pawn move cannot come back
+    fn is_draw(&self, position: &Position) -> bool {
+        if position.halfmoves >= 100 {
+            return true;
+        }
+        self.history
+            .iter()
+            .rev()
+            .take(position.halfmoves)
+            .any(|hash| *hash == position.hash)
+    }
+
+    fn negamax(&mut self, position: &mut Position, depth: u8, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        if self.check_abort() {
+            return 0;
+        }
+        if self.is_draw(position) {
+            return 0;
+        }
+        if depth == 0 {
+            return self.quiescence(position, alpha, beta);
+        }
+        let hash = position.hash;
+        let mut hash_move = None;
+        if let Some(entry) = self.table.probe(hash, ply) {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return beta,
+                    Bound::Upper if entry.score <= alpha => return alpha,
+                    _ => {},
+                }
+            }
+            hash_move = entry.best_move;
+        }
+
+        let color = position.color.clone();
+        let mut moves = get_legal_moves(&position.board, &color, &position.enpassant, &position.castling);
+        if moves.is_empty() {
+            return match is_king_attacked(&position.board, &color) {
+                true => -MATE + ply,
+                false => 0,
+            };
+        }
+        order_moves(&mut moves, &hash_move);
+
+        let mut best_move = None;
+        for mov in moves {
+            self.history.push(hash);
+            let undo = position.make_move(&mov);
+            let score = -self.negamax(position, depth - 1, ply + 1, -beta, -alpha);
+            position.unmake_move(&mov, undo);
+            self.history.pop();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                self.table.store(hash, depth, Bound::Lower, beta, Some(mov), ply);
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mov);
+            }
+        }
+
+        let bound = match best_move {
+            Some(_) => Bound::Exact,
+            None => Bound::Upper,
+        };
+        self.table.store(hash, depth, bound, alpha, best_move, ply);
+        alpha
+    }
+
+    // only captures are searched, so the evaluation isn't taken in the middle of an exchange
+    fn quiescence(&mut self, position: &mut Position, mut alpha: i32, beta: i32) -> i32 {
+        if self.check_abort() {
+            return 0;
+        }
+        let color = position.color.clone();
+        let stand_pat = evaluate(&position.board, &color);
+        if stand_pat >= beta {
+            return beta;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        let mut moves: Vec<Move> = get_legal_moves(&position.board, &color, &position.enpassant, &CastlingAvailability::default())
+            .into_iter()
+            .filter(|mov| mov.capture.is_some())
+            .collect();
+        order_moves(&mut moves, &None);
+
+        for mov in moves {
+            let undo = position.make_move(&mov);
+            let score = -self.quiescence(position, -beta, -alpha);
+            position.unmake_move(&mov, undo);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        alpha
+    }
+}
+
+impl Engine for AlphaBetaEngine {
+    fn get_name(&self) -> String {
+        String::from("Alpha-Beta Engine")
+    }
+
+    fn get_move(&mut self, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Option<Move> {
+        self.search(board, color, enpassant, castling, &SearchLimits::default(), &mut |_| {})
+    }
+
+    fn search(&mut self, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability, limits: &SearchLimits, info: &mut dyn FnMut(&SearchInfo)) -> Option<Move> {
+        let start = Instant::now();
+        self.nodes = 0;
+        self.aborted = false;
+        self.deadline = limits.time.map(|time| start + time);
+        self.stop = limits.stop.clone();
+        let max_depth = match (limits.depth, limits.time.is_some() || limits.infinite) {
+            (Some(depth), _) => depth,
+            (None, true) => MAX_DEPTH,
+            (None, false) => self.depth,
+        };
+
+        let mut position = Position::new(*board, color.clone(), *castling, *enpassant, limits.halfmoves, 1);
+        self.history.clone_from(&limits.history);
+        self.history.push(position.hash);
+        let mut moves = get_legal_moves(board, color, enpassant, castling);
+        if moves.is_empty() {
+            return None;
+        }
+        order_moves(&mut moves, &None);
+
+        // best move from the previous iteration is kept first, so it is searched first;
+        // results of an aborted iteration are discarded
+        for depth in 1..=max_depth {
+            let mut alpha = -INFINITY;
+            let mut best = 0;
+            for (i, mov) in moves.iter().enumerate() {
+                let undo = position.make_move(mov);
+                let score = -self.negamax(&mut position, depth - 1, 1, -INFINITY, -alpha);
+                position.unmake_move(mov, undo);
+                if self.aborted {
+                    break;
+                }
+                if score > alpha {
+                    alpha = score;
+                    best = i;
+                }
+            }
+            if self.aborted {
+                break;
+            }
+            let best_move = moves.remove(best);
+            moves.insert(0, best_move);
+            info(&SearchInfo {
+                depth,
+                score: alpha,
+                nodes: self.nodes,
+                time: start.elapsed(),
+                best_move,
+            });
+            if alpha >= MATE - depth as i32 {
+                break;
+            }
+        }
+        Some(moves.swap_remove(0))
+    }
+}
+
+fn is_king_attacked(board: &BitBoard, color: &Color) -> bool {
+    let captures = get_capture_map(board, &color.opposite());
+    board.get_king_by_color(color) & captures != 0
+}
+
+// move from the transposition table first, then captures by most valuable victim and least valuable attacker
+fn order_moves(moves: &mut [Move], hash_move: &Option<Move>) {
+    moves.sort_by_key(|mov| {
+        if let Some(hash_move) = hash_move {
+            if mov.from == hash_move.from && mov.to == hash_move.to && mov.promotion == hash_move.promotion {
+                return -1000;
+            }
+        }
+        let capture = match mov.capture {
+            Some(victim) => 100 + 10 * piece_order(&victim) - piece_order(&mov.piece),
+            None => 0,
+        };
+        let promotion = match mov.promotion {
+            Some(piece) => piece_order(&piece),
+            None => 0,
+        };
+        -(capture + promotion)
+    });
+}
+
+fn piece_order(piece: &Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    }
+}
+
+// material and piece-square tables, from the perspective of the side to move
+pub fn evaluate(board: &BitBoard, color: &Color) -> i32 {
+    let white = evaluate_pieces(board.white_pawns, 100, &PAWN_TABLE, &Color::White)
+        + evaluate_pieces(board.white_knights, 320, &KNIGHT_TABLE, &Color::White)
+        + evaluate_pieces(board.white_bishops, 330, &BISHOP_TABLE, &Color::White)
+        + evaluate_pieces(board.white_rooks, 500, &ROOK_TABLE, &Color::White)
+        + evaluate_pieces(board.white_queens, 900, &QUEEN_TABLE, &Color::White)
+        + evaluate_pieces(board.white_king, 0, &KING_TABLE, &Color::White);
+    let black = evaluate_pieces(board.black_pawns, 100, &PAWN_TABLE, &Color::Black)
+        + evaluate_pieces(board.black_knights, 320, &KNIGHT_TABLE, &Color::Black)
+        + evaluate_pieces(board.black_bishops, 330, &BISHOP_TABLE, &Color::Black)
+        + evaluate_pieces(board.black_rooks, 500, &ROOK_TABLE, &Color::Black)
+        + evaluate_pieces(board.black_queens, 900, &QUEEN_TABLE, &Color::Black)
+        + evaluate_pieces(board.black_king, 0, &KING_TABLE, &Color::Black);
+    match color {
+        Color::White => white - black,
+        Color::Black => black - white,
+    }
+}
+
+fn evaluate_pieces(pieces: Bitboard, value: i32, table: &[i32; 64], color: &Color) -> i32 {
+    let mut result = 0;
+    for square in pieces {
+        let square = square.index();
+        // tables start at a8, which is the last bit; black pieces are mirrored vertically
+        let index = match color {
+            Color::White => 63 - square,
+            Color::Black => 63 - (square ^ 56),
+        };
+        result += value + table[index];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::{generate_bit_board, rules::move_to_string};
+
+    use super::*;
+
+    fn best_move(fen: &str, depth: u8) -> String {
+        let fen = generate_bit_board(fen).unwrap();
+        let mut board = fen.board;
+        let mut engine = AlphaBetaEngine::new(depth, 1);
+        let mov = engine.get_move(&mut board, &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        move_to_string(&board, &mov, &fen.color)
+    }
+
+    #[test]
+    fn test_evaluate_starting_position() {
+        let fen = generate_bit_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(evaluate(&fen.board, &Color::White), 0);
+        assert_eq!(evaluate(&fen.board, &Color::Black), 0);
+    }
+
+    #[test]
+    fn test_evaluate_material() {
+        let fen = generate_bit_board("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert!(evaluate(&fen.board, &Color::White) > 800);
+        assert!(evaluate(&fen.board, &Color::Black) < -800);
+    }
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        assert_eq!(best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3), "Ra8#");
+        assert_eq!(best_move("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", 2), "Ra1#");
+    }
+
+    #[test]
+    fn test_captures_hanging_queen() {
+        assert_eq!(best_move("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 2), "Rxd5");
+    }
+
+    #[test]
+    fn test_avoids_defended_pawn() {
+        assert_ne!(best_move("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", 2), "Qxd5");
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        let fen = generate_bit_board("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let mut board = fen.board;
+        let mut engine = AlphaBetaEngine::new(2, 1);
+        assert_eq!(engine.get_move(&mut board, &fen.color, &fen.enpassant, &fen.castling), None);
+    }
+
+    #[test]
+    fn test_scores_repetition_as_draw() {
+        let position = Position::from_fen("1n5k/8/8/8/8/8/3R4/3QK3 b - - 10 40").unwrap();
+        let repeated = Position::from_fen("7k/8/n7/8/8/8/3R4/3QK3 w - - 11 41").unwrap();
+        let mut board = position.board;
+        let mut engine = AlphaBetaEngine::new(2, 1);
+        let limits = SearchLimits { depth: Some(2), halfmoves: position.halfmoves, ..Default::default() };
+        let mov = engine.search(&mut board, &position.color, &position.enpassant, &position.castling, &limits, &mut |_| {}).unwrap();
+        assert_ne!(move_to_string(&board, &mov, &position.color), "Na6");
+
+        // the side that is behind goes for the draw
+        let mut engine = AlphaBetaEngine::new(2, 1);
+        let limits = SearchLimits { history: vec![repeated.hash], ..limits };
+        let mov = engine.search(&mut board, &position.color, &position.enpassant, &position.castling, &limits, &mut |_| {}).unwrap();
+        assert_eq!(move_to_string(&board, &mov, &position.color), "Na6");
+    }
+
+    #[test]
+    fn test_search_reports_every_depth() {
+        let fen = generate_bit_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut board = fen.board;
+        let mut engine = AlphaBetaEngine::new(4, 1);
+        let limits = SearchLimits { depth: Some(3), ..Default::default() };
+        let mut depths = Vec::new();
+        engine.search(&mut board, &fen.color, &fen.enpassant, &fen.castling, &limits, &mut |info| depths.push(info.depth));
+        assert_eq!(depths, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_search_stops() {
+        let fen = generate_bit_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut board = fen.board;
+        let mut engine = AlphaBetaEngine::new(4, 1);
+        let limits = SearchLimits { infinite: true, stop: Some(Arc::new(AtomicBool::new(true))), ..Default::default() };
+        let mut depths = Vec::new();
+        engine.search(&mut board, &fen.color, &fen.enpassant, &fen.castling, &limits, &mut |info| depths.push(info.depth));
+        // the flag is only checked every few thousand nodes
+        assert!(depths.len() < 6);
+    }
+}
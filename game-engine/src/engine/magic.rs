@@ -0,0 +1,266 @@
+use once_cell::sync::Lazy;
+
+use super::square::{Bitboard, Square};
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// found with find_magic from a fixed seed, see test_magics_can_be_found
+const ROOK_MAGICS: [u64; 64] = [
+    0x008000908064C000, 0x0040200040001000, 0x0180100080A0010A, 0x8880041000800800,
+    0x1200100201200804, 0x0200020004011008, 0x2180010000800600, 0x0200005088210204,
+    0x0400800040008021, 0x0400400020005000, 0x8240801000200080, 0x8611001004200900,
+    0x008180800C001800, 0x0100800200800400, 0x0A02000102000408, 0x8020802300104280,
+    0x0080004000402000, 0xE010104000402000, 0x0800808010002000, 0xA280210008100100,
+    0x0001818014000800, 0xA002010100080400, 0x0080240001020870, 0x0001020004048845,
+    0x0081826280004004, 0x2020810900284000, 0x0200100080802000, 0x0200080080100080,
+    0x8083080100100500, 0x4406000901000400, 0x0005020080800100, 0x0090204200008114,
+    0x0010400094800420, 0x0900804000802002, 0x0201001841002000, 0x4100080080801000,
+    0x4540040080800800, 0x0002001004040020, 0x0281195814001002, 0x1240800040800100,
+    0x0880042000524004, 0x02C080410206002C, 0x0801200241050010, 0x8400080010008080,
+    0x0008000500090010, 0x0082009084020008, 0x4012000108020004, 0x9000104D08860004,
+    0x2004204114800100, 0x0148802112400300, 0x0202842000100880, 0x001B080080900080,
+    0x001A002008100600, 0x0004008004020080, 0x5181000600040300, 0x0000044401128A00,
+    0x8044110480002441, 0x2008110084402202, 0x90806005090010C1, 0x000420310A004A42,
+    0x0023001004020801, 0x0882001008040102, 0x000230088118020C, 0x0000019025040042,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x10102002004A1420, 0x8020040400584008, 0x10510800811201C8, 0x5204042080000088,
+    0x2204106880000002, 0x1401042004000000, 0x0400880410042004, 0x0028208200A02020,
+    0x1500241990010E00, 0x8001200182020A40, 0x40004101030B0000, 0x8002041042000100,
+    0x4010011041020038, 0x0000010421044000, 0x1500210808020A00, 0x8000088400880520,
+    0x0405004010040100, 0x1005823210040108, 0x2708008102040011, 0x4048200404009100,
+    0x0018104101400024, 0x0003000601190101, 0x8004803108491000, 0x8014241200820800,
+    0x0006E080100C3040, 0x0501044A11041800, 0x9020300008004045, 0x0894080000220040,
+    0x1001010083104000, 0x5004030040900080, 0x000400422C012400, 0x0002128698404812,
+    0x1010108404900440, 0x0928021182084100, 0x2006080409020024, 0x1010202020180080,
+    0xA010008200202200, 0x2098015100019004, 0x0002041440810811, 0x802A02020000B098,
+    0x0009015090004060, 0x4000821082081001, 0x0100210040420800, 0x0800004010488A00,
+    0x2000081104004040, 0x4C8E029015000082, 0x0420340322224842, 0x1298260043400210,
+    0x0000822802400008, 0x00008A0101600000, 0x3040003412080021, 0x3040290220884800,
+    0x4A1500401041004A, 0x8010200282020781, 0x0020203142209091, 0x0070300600902110,
+    0x0040808800B62048, 0x0000810400C44420, 0x00080400440C0441, 0x8340080020840411,
+    0x0000000104208200, 0x0000800810D00080, 0x0400530411080200, 0x4040702400932244,
+];
+
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+// fancy magics: attacks of all squares share one table, each square uses only as many entries as it needs
+struct MagicTable {
+    magics: Vec<Magic>,
+    attacks: Vec<u64>,
+}
+
+impl MagicTable {
+    fn new(directions: &[(i8, i8)], magic_numbers: &[u64; 64]) -> MagicTable {
+        let mut magics = Vec::with_capacity(64);
+        let mut attacks = Vec::new();
+        for (square, magic) in magic_numbers.iter().enumerate() {
+            let mask = get_relevant_mask(square, directions);
+            let shift = 64 - mask.count_ones();
+            let offset = attacks.len();
+            attacks.resize(offset + (1 << mask.count_ones()), 0);
+            // enumerates all subsets of the mask
+            let mut occupied: u64 = 0;
+            loop {
+                let index = (occupied.wrapping_mul(*magic) >> shift) as usize;
+                attacks[offset + index] = get_sliding_attacks(square, occupied, directions);
+                occupied = occupied.wrapping_sub(mask) & mask;
+                if occupied == 0 {
+                    break;
+                }
+            }
+            magics.push(Magic { mask, magic: *magic, shift, offset });
+        }
+        MagicTable { magics, attacks }
+    }
+
+    fn get_attacks(&self, square: usize, occupied: u64) -> u64 {
+        let magic = &self.magics[square];
+        let index = ((occupied & magic.mask).wrapping_mul(magic.magic) >> magic.shift) as usize;
+        self.attacks[magic.offset + index]
+    }
+}
+
+static ROOK_TABLE: Lazy<MagicTable> = Lazy::new(|| MagicTable::new(&ROOK_DIRECTIONS, &ROOK_MAGICS));
+static BISHOP_TABLE: Lazy<MagicTable> = Lazy::new(|| MagicTable::new(&BISHOP_DIRECTIONS, &BISHOP_MAGICS));
+
+pub fn get_rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    Bitboard(ROOK_TABLE.get_attacks(square.index(), occupied.0))
+}
+
+pub fn get_bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    Bitboard(BISHOP_TABLE.get_attacks(square.index(), occupied.0))
+}
+
+pub fn get_queen_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    get_rook_attacks(square, occupied) | get_bishop_attacks(square, occupied)
+}
+
+// square is rank * 8 + file, with files counted from h
+fn get_sliding_attacks(square: usize, occupied: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for (rank_step, file_step) in directions {
+        let mut rank = (square / 8) as i8 + rank_step;
+        let mut file = (square % 8) as i8 + file_step;
+        while (0..8).contains(&rank) && (0..8).contains(&file) {
+            let target = 1 << (rank * 8 + file);
+            attacks |= target;
+            if occupied & target != 0 {
+                break;
+            }
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+    attacks
+}
+
+// squares on the edge don't change the attacks, so they are left out of the mask
+fn get_relevant_mask(square: usize, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+    for (rank_step, file_step) in directions {
+        let mut rank = (square / 8) as i8 + rank_step;
+        let mut file = (square % 8) as i8 + file_step;
+        while (0..8).contains(&(rank + rank_step)) && (0..8).contains(&(file + file_step)) {
+            mask |= 1 << (rank * 8 + file);
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Random {
+        state: u64,
+    }
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.state ^= self.state >> 12;
+            self.state ^= self.state << 25;
+            self.state ^= self.state >> 27;
+            self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        // candidates with few bits set are much more likely to be magic
+        fn sparse(&mut self) -> u64 {
+            self.next() & self.next() & self.next()
+        }
+    }
+
+    fn find_magic(square: usize, directions: &[(i8, i8)], random: &mut Random) -> u64 {
+        let mask = get_relevant_mask(square, directions);
+        let shift = 64 - mask.count_ones();
+        let size = 1 << mask.count_ones();
+        let mut occupancies = Vec::with_capacity(size);
+        let mut reference = Vec::with_capacity(size);
+        // enumerates all subsets of the mask
+        let mut occupied: u64 = 0;
+        loop {
+            occupancies.push(occupied);
+            reference.push(get_sliding_attacks(square, occupied, directions));
+            occupied = occupied.wrapping_sub(mask) & mask;
+            if occupied == 0 {
+                break;
+            }
+        }
+
+        let mut table = vec![0; size];
+        // attempt in which the entry was last written, so the table doesn't have to be cleared
+        let mut epoch = vec![0; size];
+        for attempt in 1.. {
+            let magic = random.sparse();
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+            let mut failed = false;
+            for (occupied, attacks) in occupancies.iter().zip(reference.iter()) {
+                let index = (occupied.wrapping_mul(magic) >> shift) as usize;
+                if epoch[index] < attempt {
+                    epoch[index] = attempt;
+                    table[index] = *attacks;
+                } else if table[index] != *attacks {
+                    failed = true;
+                    break;
+                }
+            }
+            if !failed {
+                return magic;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn test_magics_can_be_found() {
+        // searching all squares takes a few seconds in debug builds, so only the corners are checked
+        let mut random = Random { state: 0x2545_F491_4F6C_DD1D };
+        for square in [0, 7, 56, 63] {
+            let magic = find_magic(square, &ROOK_DIRECTIONS, &mut random);
+            let table = MagicTable::new(&ROOK_DIRECTIONS, &[magic; 64]);
+            let occupied = 0x0042_0000_0000_0081;
+            assert_eq!(table.get_attacks(square, occupied), get_sliding_attacks(square, occupied, &ROOK_DIRECTIONS));
+        }
+    }
+
+    #[test]
+    fn test_embedded_magics_cover_all_occupancies() {
+        for (directions, table) in [(&ROOK_DIRECTIONS, &*ROOK_TABLE), (&BISHOP_DIRECTIONS, &*BISHOP_TABLE)] {
+            for square in 0..64 {
+                let mask = get_relevant_mask(square, directions);
+                let mut occupied: u64 = 0;
+                loop {
+                    assert_eq!(table.get_attacks(square, occupied), get_sliding_attacks(square, occupied, directions));
+                    occupied = occupied.wrapping_sub(mask) & mask;
+                    if occupied == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_relevant_mask_size() {
+        assert_eq!(get_relevant_mask(0, &ROOK_DIRECTIONS).count_ones(), 12);
+        assert_eq!(get_relevant_mask(27, &ROOK_DIRECTIONS).count_ones(), 10);
+        assert_eq!(get_relevant_mask(0, &BISHOP_DIRECTIONS).count_ones(), 6);
+        assert_eq!(get_relevant_mask(27, &BISHOP_DIRECTIONS).count_ones(), 9);
+    }
+
+    #[test]
+    fn test_attacks_match_ray_walk() {
+        let mut random = Random { state: 42 };
+        for _ in 0..2000 {
+            let occupied = random.next() & random.next();
+            for index in 0..64 {
+                let square = Square::from_index(index as u8).unwrap();
+                assert_eq!(get_rook_attacks(square, Bitboard(occupied)), get_sliding_attacks(index, occupied, &ROOK_DIRECTIONS));
+                assert_eq!(get_bishop_attacks(square, Bitboard(occupied)), get_sliding_attacks(index, occupied, &BISHOP_DIRECTIONS));
+            }
+        }
+    }
+
+    #[test]
+    fn test_queen_attacks() {
+        let square = |name: &str| name.parse::<Square>().unwrap();
+        let occupied = square("d6").bitboard() | square("f4").bitboard();
+        let attacks = get_queen_attacks(square("d4"), occupied);
+        assert!(attacks.contains(square("d6")));
+        assert!(!attacks.contains(square("d7")));
+        assert!(attacks.contains(square("f4")));
+        assert!(!attacks.contains(square("g4")));
+        assert_eq!(attacks.count(), 23);
+    }
+}
@@ -1,49 +1,139 @@
-use std::u64;
+use std::{str::FromStr, sync::{atomic::AtomicBool, Arc}, time::Duration};
+
+use serde::{Deserialize, Serialize};
 
 use crate::Color;
 
-use self::rules::{field_to_num, Move, Piece};
+use self::{rules::{get_capture_map, piece_to_letter, Move, Piece}, square::{Bitboard, File, Rank, Square}};
+mod alpha_beta_engine;
+pub mod magic;
+pub mod perft;
+pub mod pgn;
+pub mod position;
 mod random_engine;
 pub mod rules;
+pub mod square;
+pub mod transposition;
+pub mod zobrist;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "Pieces", into = "Pieces")]
 pub struct BitBoard {
-    pub white_pawns: u64,
-    pub white_knights: u64,
-    pub white_bishops: u64,
-    pub white_rooks: u64,
-    pub white_queens: u64,
-    pub white_king: u64,
-
-    pub black_pawns: u64,
-    pub black_knights: u64,
-    pub black_bishops: u64,
-    pub black_rooks: u64,
-    pub black_queens: u64,
-    pub black_king: u64,
+    pub white_pawns: Bitboard,
+    pub white_knights: Bitboard,
+    pub white_bishops: Bitboard,
+    pub white_rooks: Bitboard,
+    pub white_queens: Bitboard,
+    pub white_king: Bitboard,
+
+    pub black_pawns: Bitboard,
+    pub black_knights: Bitboard,
+    pub black_bishops: Bitboard,
+    pub black_rooks: Bitboard,
+    pub black_queens: Bitboard,
+    pub black_king: Bitboard,
+
+    // caches derived from the bitboards above, kept in sync by apply_move and undo_move
+    white: Bitboard,
+    black: Bitboard,
+    mailbox: [Option<Piece>; 64],
+}
+
+// bitboards without the caches, used to build boards and to serialize them
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Pieces {
+    white_pawns: Bitboard,
+    white_knights: Bitboard,
+    white_bishops: Bitboard,
+    white_rooks: Bitboard,
+    white_queens: Bitboard,
+    white_king: Bitboard,
+
+    black_pawns: Bitboard,
+    black_knights: Bitboard,
+    black_bishops: Bitboard,
+    black_rooks: Bitboard,
+    black_queens: Bitboard,
+    black_king: Bitboard,
+}
+
+impl From<Pieces> for BitBoard {
+    fn from(pieces: Pieces) -> BitBoard {
+        let mut board = BitBoard {
+            white_pawns: pieces.white_pawns,
+            white_knights: pieces.white_knights,
+            white_bishops: pieces.white_bishops,
+            white_rooks: pieces.white_rooks,
+            white_queens: pieces.white_queens,
+            white_king: pieces.white_king,
+            black_pawns: pieces.black_pawns,
+            black_knights: pieces.black_knights,
+            black_bishops: pieces.black_bishops,
+            black_rooks: pieces.black_rooks,
+            black_queens: pieces.black_queens,
+            black_king: pieces.black_king,
+            white: Bitboard::EMPTY,
+            black: Bitboard::EMPTY,
+            mailbox: [None; 64],
+        };
+        board.white = board.white_pawns | board.white_knights | board.white_bishops | board.white_rooks | board.white_queens | board.white_king;
+        board.black = board.black_pawns | board.black_knights | board.black_bishops | board.black_rooks | board.black_queens | board.black_king;
+        for piece in [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King] {
+            for square in board.get_bitboard_by_piece(&piece, &Color::White) | board.get_bitboard_by_piece(&piece, &Color::Black) {
+                board.mailbox[square.index()] = Some(piece);
+            }
+        }
+        board
+    }
 }
 
+impl From<BitBoard> for Pieces {
+    fn from(board: BitBoard) -> Pieces {
+        Pieces {
+            white_pawns: board.white_pawns,
+            white_knights: board.white_knights,
+            white_bishops: board.white_bishops,
+            white_rooks: board.white_rooks,
+            white_queens: board.white_queens,
+            white_king: board.white_king,
+            black_pawns: board.black_pawns,
+            black_knights: board.black_knights,
+            black_bishops: board.black_bishops,
+            black_rooks: board.black_rooks,
+            black_queens: board.black_queens,
+            black_king: board.black_king,
+        }
+    }
+}
+
+const BACK_RANKS: Bitboard = Bitboard(0xFF00_0000_0000_00FF);
+const ALPHA_BETA_DEPTH: u8 = 4;
+const TRANSPOSITION_TABLE_SIZE: usize = 16;
+// time kept in reserve when the move time is computed from the clock, in milliseconds
+const MOVE_OVERHEAD: u64 = 50;
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
 pub struct FEN {
     pub board: BitBoard,
     pub color: Color,
     pub castling: CastlingAvailability,
-    pub enpassant: Option<u8>,
+    pub enpassant: Option<Square>,
     pub halfmoves: usize,
     pub moves: usize,
 }
 
 impl CastlingAvailability {
     pub fn after_move(&self, mov: &Move, color: &Color) -> CastlingAvailability {
-        let white_kingside_rook = 0;
-        let white_queenside_rook = 7;
-        let black_kingside_rook = 56;
-        let black_queenside_rook = 63;
+        let white_kingside_rook = Square::new(File::H, Rank::FIRST);
+        let white_queenside_rook = Square::new(File::A, Rank::FIRST);
+        let black_kingside_rook = Square::new(File::H, Rank::EIGHTH);
+        let black_queenside_rook = Square::new(File::A, Rank::EIGHTH);
 
         let black_queenside = match self.black_queenside {
             false => false,
             true => match (&mov.piece, color) {
                 (Piece::King, Color::Black) => false,
-                (Piece::Rook, Color::Black) => !(mov.from == black_queenside_rook),
+                (Piece::Rook, Color::Black) => mov.from != black_queenside_rook,
                 (_, Color::White) => !(mov.capture.is_some() && mov.to == black_queenside_rook),
                 _  => true,
             },
@@ -52,7 +142,7 @@ impl CastlingAvailability {
             false => false,
             true => match (&mov.piece, color) {
                 (Piece::King, Color::Black) => false,
-                (Piece::Rook, Color::Black) => !(mov.from == black_kingside_rook),
+                (Piece::Rook, Color::Black) => mov.from != black_kingside_rook,
                 (_, Color::White) => !(mov.capture.is_some() && mov.to == black_kingside_rook),
                 _  => true,
             },
@@ -62,7 +152,7 @@ impl CastlingAvailability {
             false => false,
             true => match (&mov.piece, color) {
                 (Piece::King, Color::White) => false,
-                (Piece::Rook, Color::White) => !(mov.from == white_queenside_rook),
+                (Piece::Rook, Color::White) => mov.from != white_queenside_rook,
                 (_, Color::Black) => !(mov.capture.is_some() && mov.to == white_queenside_rook),
                 _  => true,
             },
@@ -71,7 +161,7 @@ impl CastlingAvailability {
             false => false,
             true => match (&mov.piece, color) {
                 (Piece::King, Color::White) => false,
-                (Piece::Rook, Color::White) => !(mov.from == white_kingside_rook),
+                (Piece::Rook, Color::White) => mov.from != white_kingside_rook,
                 (_, Color::Black) => !(mov.capture.is_some() && mov.to == white_kingside_rook),
                 _  => true,
             },
@@ -99,14 +189,14 @@ impl CastlingAvailability {
         if self.black_queenside {
             fen.push('q');
         }
-        if fen == "" {
+        if fen.is_empty() {
             fen.push('-');
         }
         fen
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CastlingAvailability {
     pub black_queenside: bool,
     pub black_kingside: bool,
@@ -114,126 +204,264 @@ pub struct CastlingAvailability {
     pub white_kingside: bool,
 }
 
-pub fn generate_bit_board(fen_board: &String) -> Result<FEN, String> {
-    let mut fen_notation = fen_board.split(" ");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Board, Color, Castling, Enpassant, Halfmoves, Moves,
+}
 
-    // board
-    let board_def = fen_notation.next();
-    let Some(board_def) = board_def else {
-        return  Err("No board representation".into())
-    };
+impl std::fmt::Display for FenField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FenField::Board => "board",
+            FenField::Color => "color",
+            FenField::Castling => "castling",
+            FenField::Enpassant => "enpassant",
+            FenField::Halfmoves => "halfmove clock",
+            FenField::Moves => "move clock",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-    let board = generate_board_from_fen(board_def);
-    let Ok(board) = board else {
-        return Err(board.err().unwrap())
-    };
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField { field: FenField, offset: usize },
+    TooManyFields { offset: usize },
+    InvalidValue { field: FenField, offset: usize },
+    InvalidPiece { piece: char, offset: usize },
+    InvalidRankLength { rank: u8, squares: u32, offset: usize },
+    InvalidRankCount { ranks: usize, offset: usize },
+    InvalidKingCount { color: Color, count: u32 },
+    PawnOnBackRank { square: Square },
+    InconsistentEnpassant { square: Square },
+    InconsistentCastling { right: char },
+    OpponentInCheck,
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField { field, offset } => write!(f, "No {} information at offset {}", field, offset),
+            FenError::TooManyFields { offset } => write!(f, "Unexpected field at offset {}", offset),
+            FenError::InvalidValue { field, offset } => write!(f, "Incorrect {} at offset {}", field, offset),
+            FenError::InvalidPiece { piece, offset } => write!(f, "Unknown piece '{}' at offset {}", piece, offset),
+            FenError::InvalidRankLength { rank, squares, offset } => write!(f, "Rank {} has {} squares instead of 8 at offset {}", rank, squares, offset),
+            FenError::InvalidRankCount { ranks, offset } => write!(f, "Board has {} ranks instead of 8 at offset {}", ranks, offset),
+            FenError::InvalidKingCount { color, count } => write!(f, "{:?} has {} kings instead of 1", color, count),
+            FenError::PawnOnBackRank { square } => write!(f, "Pawn on back rank at {}", square),
+            FenError::InconsistentEnpassant { square } => write!(f, "Enpassant square {} is not possible in this position", square),
+            FenError::InconsistentCastling { right } => write!(f, "Castling right {} does not match king and rook placement", right),
+            FenError::OpponentInCheck => write!(f, "Side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl FEN {
+    // semantic checks on top of the syntax checks done by generate_bit_board
+    pub fn validate(&self) -> Result<(), FenError> {
+        let board = &self.board;
+        let white_kings = board.white_king.count();
+        if white_kings != 1 {
+            return Err(FenError::InvalidKingCount { color: Color::White, count: white_kings });
+        }
+        let black_kings = board.black_king.count();
+        if black_kings != 1 {
+            return Err(FenError::InvalidKingCount { color: Color::Black, count: black_kings });
+        }
+
+        let back_rank_pawns = (board.white_pawns | board.black_pawns) & BACK_RANKS;
+        if let Some(square) = back_rank_pawns.first() {
+            return Err(FenError::PawnOnBackRank { square });
+        }
+
+        if let Some(square) = self.enpassant {
+            let empty = !(board.get_white() | board.get_black());
+            // the pawn that made the double push stands in front of the square, and the square behind it is empty
+            let (rank, enemy_pawns, forward) = match self.color {
+                Color::White => (Rank::SIXTH, board.black_pawns, 1),
+                Color::Black => (Rank::THIRD, board.white_pawns, -1),
+            };
+            let consistent = square.rank() == rank
+                && square.offset(0, -forward).is_some_and(|pawn| enemy_pawns.contains(pawn))
+                && square.offset(0, forward).is_some_and(|origin| empty.contains(origin))
+                && empty.contains(square);
+            if !consistent {
+                return Err(FenError::InconsistentEnpassant { square });
+            }
+        }
+
+        let rights = [
+            (self.castling.white_kingside, 'K', board.white_king, board.white_rooks, Rank::FIRST, File::H),
+            (self.castling.white_queenside, 'Q', board.white_king, board.white_rooks, Rank::FIRST, File::A),
+            (self.castling.black_kingside, 'k', board.black_king, board.black_rooks, Rank::EIGHTH, File::H),
+            (self.castling.black_queenside, 'q', board.black_king, board.black_rooks, Rank::EIGHTH, File::A),
+        ];
+        for (available, right, king, rooks, rank, rook_file) in rights {
+            if available && (!king.contains(Square::new(File::E, rank)) || !rooks.contains(Square::new(rook_file, rank))) {
+                return Err(FenError::InconsistentCastling { right });
+            }
+        }
+
+        let captures = get_capture_map(board, &self.color);
+        if !(captures & board.get_king_by_color(&self.color.opposite())).is_empty() {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+}
+
+fn next_field<'a>(fields: &mut impl Iterator<Item = (usize, &'a str)>, field: FenField, end: usize) -> Result<(usize, &'a str), FenError> {
+    match fields.next() {
+        Some((offset, "")) => Err(FenError::InvalidValue { field, offset }),
+        Some(value) => Ok(value),
+        None => Err(FenError::MissingField { field, offset: end }),
+    }
+}
+
+fn parse_clock(clock: &str, field: FenField, offset: usize) -> Result<usize, FenError> {
+    if let Some(position) = clock.find(|c: char| !c.is_ascii_digit()) {
+        return Err(FenError::InvalidValue { field, offset: offset + position })
+    }
+    clock.parse::<usize>().map_err(|_| FenError::InvalidValue { field, offset })
+}
+
+pub fn generate_bit_board(fen_board: &str) -> Result<FEN, FenError> {
+    let end = fen_board.len();
+    let mut fen_notation = fen_board.split(' ').scan(0, |offset, field| {
+        let start = *offset;
+        *offset += field.len() + 1;
+        Some((start, field))
+    });
+
+    // board
+    let (_, board_def) = next_field(&mut fen_notation, FenField::Board, end)?;
+    let board = generate_board_from_fen(board_def)?;
 
     // color
-    let color = fen_notation.next();
-    let Some(color) = color else {
-        return  Err("No color information".into())
-    };
+    let (offset, color) = next_field(&mut fen_notation, FenField::Color, end)?;
     let color = match color {
         "w" => Color::White,
         "b" => Color::Black,
-        _ => return Err("Incorrect color!".into()),
+        _ => return Err(FenError::InvalidValue { field: FenField::Color, offset }),
     };
 
     // castling
-    let castling = fen_notation.next();
-    let Some(castling) = castling else {
-        return  Err("No castling information".into())
-    };
+    let (offset, castling) = next_field(&mut fen_notation, FenField::Castling, end)?;
     let castling = match castling {
         "-" => CastlingAvailability {black_kingside: false, black_queenside: false, white_kingside: false, white_queenside: false},
-        castling => CastlingAvailability {
+        castling => {
+            // rights have to be a subsequence of KQkq
+            let mut allowed = "KQkq".chars();
+            for (i, right) in castling.char_indices() {
+                if !allowed.any(|c| c == right) {
+                    return Err(FenError::InvalidValue { field: FenField::Castling, offset: offset + i });
+                }
+            }
+            CastlingAvailability {
                 black_kingside: castling.contains("k"), 
                 black_queenside: castling.contains("q"),
                 white_kingside: castling.contains("K"),
                 white_queenside: castling.contains("Q"),
-            },
+            }
+        },
     };
 
     // enpassant
-    let enpassant = fen_notation.next();
-    let Some(enpassant) = enpassant else {
-        return  Err("No enpassant information".into())
-    };
-    let enpassant = match enpassant {
-        "-" => None,
-        enpassant => Some(field_to_num(enpassant)),
+    let (offset, enpassant) = next_field(&mut fen_notation, FenField::Enpassant, end)?;
+    let enpassant = match enpassant.as_bytes() {
+        b"-" => None,
+        [b'a'..=b'h', b'3' | b'6'] => enpassant.parse().ok(),
+        [b'a'..=b'h', _] => return Err(FenError::InvalidValue { field: FenField::Enpassant, offset: offset + 1 }),
+        _ => return Err(FenError::InvalidValue { field: FenField::Enpassant, offset }),
     };
 
     // halfmove clock
-    let halfmove_clock = fen_notation.next();
-    let Some(halfmove_clock) = halfmove_clock else {
-        return  Err("No halfmove count".into())
-    };
-    let halfmoves = halfmove_clock.parse::<usize>();
-    let Ok(halfmoves) = halfmoves else {
-        return  Err("Corrupted halfmove count".into())
-    };
+    let (offset, halfmove_clock) = next_field(&mut fen_notation, FenField::Halfmoves, end)?;
+    let halfmoves = parse_clock(halfmove_clock, FenField::Halfmoves, offset)?;
 
     // move clock
-    let move_clock = fen_notation.next();
-    let Some(move_clock) = move_clock else {
-        return  Err("No move count".into())
-    };
-    let moves = move_clock.parse::<usize>();
-    let Ok(moves) = moves else {
-        return  Err("Corrupted move count".into())
-    };
+    let (offset, move_clock) = next_field(&mut fen_notation, FenField::Moves, end)?;
+    let moves = parse_clock(move_clock, FenField::Moves, offset)?;
+    if moves == 0 {
+        return Err(FenError::InvalidValue { field: FenField::Moves, offset });
+    }
 
+    if let Some((offset, _)) = fen_notation.next() {
+        return Err(FenError::TooManyFields { offset });
+    }
    
     Ok(FEN { board, color, castling, enpassant, halfmoves, moves })
 }
 
 
-fn generate_board_from_fen(board_def: &str) -> Result<BitBoard, String> {
-    let mut white_pawns = 0;
-    let mut white_knights = 0;
-    let mut white_bishops = 0;
-    let mut white_rooks = 0;
-    let mut white_queens = 0;
-    let mut white_king = 0;
-
-    let mut black_pawns = 0;
-    let mut black_knights = 0;
-    let mut black_bishops = 0;
-    let mut black_rooks = 0;
-    let mut black_queens = 0;
-    let mut black_king = 0;
+fn generate_board_from_fen(board_def: &str) -> Result<BitBoard, FenError> {
+    let mut white_pawns = Bitboard::EMPTY;
+    let mut white_knights = Bitboard::EMPTY;
+    let mut white_bishops = Bitboard::EMPTY;
+    let mut white_rooks = Bitboard::EMPTY;
+    let mut white_queens = Bitboard::EMPTY;
+    let mut white_king = Bitboard::EMPTY;
 
-    let mut index: u64 = 1 << 63;
+    let mut black_pawns = Bitboard::EMPTY;
+    let mut black_knights = Bitboard::EMPTY;
+    let mut black_bishops = Bitboard::EMPTY;
+    let mut black_rooks = Bitboard::EMPTY;
+    let mut black_queens = Bitboard::EMPTY;
+    let mut black_king = Bitboard::EMPTY;
 
+    let mut offset = 0;
+    let mut rank_count = 0;
     let ranks = board_def.split("/");
     for rank in ranks {
-        for a in rank.chars() {
-            if a.is_digit(10) {
-                let empty_spaces = a.to_digit(10).unwrap();
-                index = index >> empty_spaces;
+        rank_count += 1;
+        if rank_count > 8 {
+            return Err(FenError::InvalidRankCount { ranks: board_def.split("/").count(), offset: offset - 1 });
+        }
+        let rank_num = 9 - rank_count as u8;
+        let mut squares = 0;
+        for (i, a) in rank.char_indices() {
+            if let Some(empty_spaces) = a.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                squares += empty_spaces;
+                if squares > 8 {
+                    return Err(FenError::InvalidRankLength { rank: rank_num, squares, offset: offset + i });
+                }
                 continue;
             }
-            match a {
-                'P' => white_pawns = white_pawns | index,
-                'N' => white_knights = white_knights | index,
-                'B' => white_bishops = white_bishops | index,
-                'R' => white_rooks = white_rooks | index,
-                'Q' => white_queens = white_queens | index,
-                'K' => white_king = white_king | index,
-
-                'p' => black_pawns = black_pawns | index,
-                'n' => black_knights = black_knights | index,
-                'b' => black_bishops = black_bishops | index,
-                'r' => black_rooks = black_rooks | index,
-                'q' => black_queens = black_queens | index,
-                'k' => black_king = black_king | index,
-                _ => return Err("".into()),
+            let bitboard = match a {
+                'P' => &mut white_pawns,
+                'N' => &mut white_knights,
+                'B' => &mut white_bishops,
+                'R' => &mut white_rooks,
+                'Q' => &mut white_queens,
+                'K' => &mut white_king,
+
+                'p' => &mut black_pawns,
+                'n' => &mut black_knights,
+                'b' => &mut black_bishops,
+                'r' => &mut black_rooks,
+                'q' => &mut black_queens,
+                'k' => &mut black_king,
+                _ => return Err(FenError::InvalidPiece { piece: a, offset: offset + i }),
+            };
+            squares += 1;
+            if squares > 8 {
+                return Err(FenError::InvalidRankLength { rank: rank_num, squares, offset: offset + i });
             }
-            index = index >> 1;
+            let square = Square::new(File::ALL[squares as usize - 1], Rank::ALL[rank_num as usize - 1]);
+            *bitboard |= square.bitboard();
+        }
+        if squares != 8 {
+            return Err(FenError::InvalidRankLength { rank: rank_num, squares, offset: offset + rank.len() });
         }
+        offset += rank.len() + 1;
     }
-    Ok(BitBoard {
+    if rank_count != 8 {
+        return Err(FenError::InvalidRankCount { ranks: rank_count, offset: board_def.len() });
+    }
+    Ok(BitBoard::from(Pieces {
         white_pawns,
         white_knights,
         white_bishops,
@@ -246,42 +474,20 @@ fn generate_board_from_fen(board_def: &str) -> Result<BitBoard, String> {
         black_rooks,
         black_queens,
         black_king,
-    })
+    }))
 }
 
 impl BitBoard {
-    pub fn to_fen(self) -> String {
-        let mut index: u64 = 1 << 63;
+    pub fn to_fen(&self) -> String {
         let mut empty = 0;
         let mut result = String::from("");
-        for rank in 0..8 {
-            for _file in 0..8 {
-                let mut piece = None;
-                if self.white_pawns & index != 0  {
-                    piece = Some('P');
-                } else if self.white_knights & index != 0 {
-                    piece = Some('N');
-                } else if self.white_bishops & index != 0 {
-                    piece = Some('B');
-                } else if self.white_rooks & index != 0 {
-                    piece = Some('R');
-                } else if self.white_queens & index != 0 {
-                    piece = Some('Q');
-                } else if self.white_king & index != 0 {
-                    piece = Some('K');
-                } else if self.black_pawns & index != 0  {
-                    piece = Some('p');
-                } else if self.black_knights & index != 0 {
-                    piece = Some('n');
-                } else if self.black_bishops & index != 0 {
-                    piece = Some('b');
-                } else if self.black_rooks & index != 0 {
-                    piece = Some('r');
-                } else if self.black_queens & index != 0 {
-                    piece = Some('q');
-                } else if self.black_king & index != 0 {
-                    piece = Some('k');
-                }
+        for rank in Rank::ALL.into_iter().rev() {
+            for file in File::ALL {
+                let piece = self.piece_at(Square::new(file, rank))
+                    .map(|(color, piece)| match color {
+                        Color::White => piece_to_letter(piece),
+                        Color::Black => piece_to_letter(piece).to_ascii_lowercase(),
+                    });
 
                 if let Some(piece) = piece {
                     if empty > 0 {
@@ -292,14 +498,12 @@ impl BitBoard {
                 } else {
                     empty += 1;
                 }
-
-                index = index >> 1;
             }
             if empty > 0 {
                 result.push_str(&empty.to_string());
             }
             empty = 0;
-            if rank != 7 {
+            if rank != Rank::FIRST {
                 result.push('/');
             }
         }
@@ -307,140 +511,183 @@ impl BitBoard {
         result
     }
 
-    pub fn check_capture(&self, target: &u64, color: &Color) -> Option<Piece> {
-        match color {
-            Color::Black => {
-               if self.white_pawns & target != 0 {
-                   return Some(Piece::Pawn)
-               }
-               if self.white_knights & target != 0 {
-                   return Some(Piece::Knight)
-               }
-               if self.white_bishops & target != 0 {
-                   return Some(Piece::Bishop)
-               }
-               if self.white_rooks & target != 0 {
-                   return Some(Piece::Rook)
-               }
-               if self.white_queens & target != 0 {
-                   return Some(Piece::Queen)
-               }
-               if self.white_king & target != 0 {
-                   return Some(Piece::King) // shouldn't happen
-               }
-               None
-            },
-            Color::White => {
-               if self.black_pawns & target != 0 {
-                   return Some(Piece::Pawn)
-               }
-               if self.black_knights & target != 0 {
-                   return Some(Piece::Knight)
-               }
-               if self.black_bishops & target != 0 {
-                   return Some(Piece::Bishop)
-               }
-               if self.black_rooks & target != 0 {
-                   return Some(Piece::Rook)
-               }
-               if self.black_queens & target != 0 {
-                   return Some(Piece::Queen)
-               }
-               if self.black_king & target != 0 {
-                   return Some(Piece::King) // shouldn't happen
-               }
-               None
-            }
+    pub fn check_capture(&self, square: Square, color: &Color) -> Option<Piece> {
+        let enemy = match color {
+            Color::Black => self.white,
+            Color::White => self.black,
+        };
+        match enemy.contains(square) {
+            true => self.mailbox[square.index()],
+            false => None,
+        }
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<(Color, Piece)> {
+        let piece = self.mailbox[square.index()]?;
+        match self.white.contains(square) {
+            true => Some((Color::White, piece)),
+            false => Some((Color::Black, piece)),
         }
     }
 
-    // TODO: castling, enpassant
-    pub fn apply_move(&mut self, mov: &Move, color: &Color) -> () {
+    pub fn apply_move(&mut self, mov: &Move, color: &Color) {
+        self.update_caches(mov, color, false);
+        self.toggle_bitboards(mov, color);
+    }
+
+    // takes back a move made with apply_move
+    pub fn undo_move(&mut self, mov: &Move, color: &Color) {
+        self.update_caches(mov, color, true);
+        self.toggle_bitboards(mov, color);
+    }
+
+    // bitboards are updated with xor, so the same change makes and takes back the move
+    fn toggle_bitboards(&mut self, mov: &Move, color: &Color) {
         if let Some(promotion) = mov.promotion {
             self.apply_promotion(mov, color, &promotion);
             return;
         }
+        if mov.castling {
+            self.apply_castling(mov, color);
+        }
         if color == &Color::White {
             match mov.piece {
-                Piece::Pawn => self.white_pawns = self.white_pawns ^ (1<<mov.from | 1<<mov.to),
-                Piece::Knight => self.white_knights = self.white_knights ^ (1<<mov.from | 1<<mov.to),
-                Piece::Bishop => self.white_bishops = self.white_bishops ^ (1<<mov.from | 1<<mov.to),
-                Piece::Rook => self.white_rooks = self.white_rooks ^ (1<<mov.from | 1<<mov.to),
-                Piece::Queen => self.white_queens = self.white_queens ^ (1<<mov.from | 1<<mov.to),
-                Piece::King => self.white_king = self.white_king ^ (1<<mov.from | 1<<mov.to),
+                Piece::Pawn => self.white_pawns ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Knight => self.white_knights ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Bishop => self.white_bishops ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Rook => self.white_rooks ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Queen => self.white_queens ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::King => self.white_king ^= mov.from.bitboard() | mov.to.bitboard(),
             }
-            match mov.capture { // TODO: enpassant
+            if mov.enpassant {
+                self.black_pawns ^= get_enpassant_capture(mov).bitboard();
+                return;
+            }
+            match mov.capture {
                 None => {},
-                Some(Piece::Pawn) => self.black_pawns = self.black_pawns ^ 1<<mov.to,
-                Some(Piece::Knight) => self.black_knights = self.black_knights ^ 1<<mov.to,
-                Some(Piece::Bishop) => self.black_bishops = self.black_bishops ^ 1<<mov.to,
-                Some(Piece::Rook) => self.black_rooks = self.black_rooks ^ 1<<mov.to,
-                Some(Piece::Queen) => self.black_queens = self.black_queens ^ 1<<mov.to,
-                Some(Piece::King) => self.black_king = self.black_king ^ 1<<mov.to,
+                Some(Piece::Pawn) => self.black_pawns ^= mov.to.bitboard(),
+                Some(Piece::Knight) => self.black_knights ^= mov.to.bitboard(),
+                Some(Piece::Bishop) => self.black_bishops ^= mov.to.bitboard(),
+                Some(Piece::Rook) => self.black_rooks ^= mov.to.bitboard(),
+                Some(Piece::Queen) => self.black_queens ^= mov.to.bitboard(),
+                Some(Piece::King) => self.black_king ^= mov.to.bitboard(),
             }
         } else {
             match mov.piece {
-                Piece::Pawn => self.black_pawns = self.black_pawns ^ (1<<mov.from | 1<<mov.to),
-                Piece::Knight => self.black_knights = self.black_knights ^ (1<<mov.from | 1<<mov.to),
-                Piece::Bishop => self.black_bishops = self.black_bishops ^ (1<<mov.from | 1<<mov.to),
-                Piece::Rook => self.black_rooks = self.black_rooks ^ (1<<mov.from | 1<<mov.to),
-                Piece::Queen => self.black_queens = self.black_queens ^ (1<<mov.from | 1<<mov.to),
-                Piece::King => self.black_king = self.black_king ^ (1<<mov.from | 1<<mov.to),
+                Piece::Pawn => self.black_pawns ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Knight => self.black_knights ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Bishop => self.black_bishops ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Rook => self.black_rooks ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::Queen => self.black_queens ^= mov.from.bitboard() | mov.to.bitboard(),
+                Piece::King => self.black_king ^= mov.from.bitboard() | mov.to.bitboard(),
             }
-            match mov.capture { // TODO: enpassant
+            if mov.enpassant {
+                self.white_pawns ^= get_enpassant_capture(mov).bitboard();
+                return;
+            }
+            match mov.capture {
                 None => {},
-                Some(Piece::Pawn) => self.white_pawns = self.white_pawns ^ 1<<mov.to,
-                Some(Piece::Knight) => self.white_knights = self.white_knights ^ 1<<mov.to,
-                Some(Piece::Bishop) => self.white_bishops = self.white_bishops ^ 1<<mov.to,
-                Some(Piece::Rook) => self.white_rooks = self.white_rooks ^ 1<<mov.to,
-                Some(Piece::Queen) => self.white_queens = self.white_queens ^ 1<<mov.to,
-                Some(Piece::King) => self.white_king = self.white_king ^ 1<<mov.to,
+                Some(Piece::Pawn) => self.white_pawns ^= mov.to.bitboard(),
+                Some(Piece::Knight) => self.white_knights ^= mov.to.bitboard(),
+                Some(Piece::Bishop) => self.white_bishops ^= mov.to.bitboard(),
+                Some(Piece::Rook) => self.white_rooks ^= mov.to.bitboard(),
+                Some(Piece::Queen) => self.white_queens ^= mov.to.bitboard(),
+                Some(Piece::King) => self.white_king ^= mov.to.bitboard(),
             }
         }
     }
 
-    fn apply_promotion(&mut self, mov: &Move, color: &Color, promotion: &Piece) -> () {
+    // the captured piece is taken from the move, so captures have to be marked
+    fn update_caches(&mut self, mov: &Move, color: &Color, undo: bool) {
+        let (own, enemy) = match color {
+            Color::White => (&mut self.white, &mut self.black),
+            Color::Black => (&mut self.black, &mut self.white),
+        };
+        let captured = match mov.enpassant {
+            true => get_enpassant_capture(mov),
+            false => mov.to,
+        };
+        *own ^= mov.from.bitboard() | mov.to.bitboard();
+        if mov.capture.is_some() {
+            *enemy ^= captured.bitboard();
+        }
+        match undo {
+            false => {
+                self.mailbox[mov.from.index()] = None;
+                self.mailbox[captured.index()] = None;
+                self.mailbox[mov.to.index()] = Some(mov.promotion.unwrap_or(mov.piece));
+            },
+            true => {
+                self.mailbox[mov.to.index()] = None;
+                self.mailbox[captured.index()] = mov.capture;
+                self.mailbox[mov.from.index()] = Some(mov.piece);
+            },
+        }
+        if mov.castling {
+            let (rook_from, rook_to) = get_castling_rook_squares(mov);
+            match color {
+                Color::White => self.white ^= rook_from.bitboard() | rook_to.bitboard(),
+                Color::Black => self.black ^= rook_from.bitboard() | rook_to.bitboard(),
+            }
+            let (rook_from, rook_to) = match undo {
+                false => (rook_from, rook_to),
+                true => (rook_to, rook_from),
+            };
+            self.mailbox[rook_from.index()] = None;
+            self.mailbox[rook_to.index()] = Some(Piece::Rook);
+        }
+    }
+
+    fn apply_castling(&mut self, mov: &Move, color: &Color) {
+        let (rook_from, rook_to) = get_castling_rook_squares(mov);
+        match color {
+            Color::White => self.white_rooks ^= rook_from.bitboard() | rook_to.bitboard(),
+            Color::Black => self.black_rooks ^= rook_from.bitboard() | rook_to.bitboard(),
+        }
+    }
+
+    fn apply_promotion(&mut self, mov: &Move, color: &Color, promotion: &Piece) {
         if color == &Color::White {
-            self.white_pawns = self.white_pawns ^ 1<<mov.from;
+            self.white_pawns ^= mov.from.bitboard();
             match promotion {
-                Piece::Knight => self.white_knights = self.white_knights ^ 1<<mov.to,
-                Piece::Bishop => self.white_bishops = self.white_bishops ^ 1<<mov.to,
-                Piece::Rook => self.white_rooks = self.white_rooks ^ 1<<mov.to,
-                Piece::Queen => self.white_queens = self.white_queens ^ 1<<mov.to,
+                Piece::Knight => self.white_knights ^= mov.to.bitboard(),
+                Piece::Bishop => self.white_bishops ^= mov.to.bitboard(),
+                Piece::Rook => self.white_rooks ^= mov.to.bitboard(),
+                Piece::Queen => self.white_queens ^= mov.to.bitboard(),
                 _ => {},
             }
             match mov.capture {
                 None => {},
-                Some(Piece::Pawn) => self.black_pawns = self.black_pawns ^ 1<<mov.to,
-                Some(Piece::Knight) => self.black_knights = self.black_knights ^ 1<<mov.to,
-                Some(Piece::Bishop) => self.black_bishops = self.black_bishops ^ 1<<mov.to,
-                Some(Piece::Rook) => self.black_rooks = self.black_rooks ^ 1<<mov.to,
-                Some(Piece::Queen) => self.black_queens = self.black_queens ^ 1<<mov.to,
-                Some(Piece::King) => self.black_king = self.black_king ^ 1<<mov.to,
+                Some(Piece::Pawn) => self.black_pawns ^= mov.to.bitboard(),
+                Some(Piece::Knight) => self.black_knights ^= mov.to.bitboard(),
+                Some(Piece::Bishop) => self.black_bishops ^= mov.to.bitboard(),
+                Some(Piece::Rook) => self.black_rooks ^= mov.to.bitboard(),
+                Some(Piece::Queen) => self.black_queens ^= mov.to.bitboard(),
+                Some(Piece::King) => self.black_king ^= mov.to.bitboard(),
             }
         } else {
-            self.black_pawns = self.black_pawns ^ 1<<mov.from;
+            self.black_pawns ^= mov.from.bitboard();
             match promotion {
-                Piece::Knight => self.black_knights = self.black_knights ^ 1<<mov.to,
-                Piece::Bishop => self.black_bishops = self.black_bishops ^ 1<<mov.to,
-                Piece::Rook => self.black_rooks = self.black_rooks ^ 1<<mov.to,
-                Piece::Queen => self.black_queens = self.black_queens ^ 1<<mov.to,
+                Piece::Knight => self.black_knights ^= mov.to.bitboard(),
+                Piece::Bishop => self.black_bishops ^= mov.to.bitboard(),
+                Piece::Rook => self.black_rooks ^= mov.to.bitboard(),
+                Piece::Queen => self.black_queens ^= mov.to.bitboard(),
                 _ => {},
             }
             match mov.capture {
                 None => {},
-                Some(Piece::Pawn) => self.white_pawns = self.white_pawns ^ 1<<mov.to,
-                Some(Piece::Knight) => self.white_knights = self.white_knights ^ 1<<mov.to,
-                Some(Piece::Bishop) => self.white_bishops = self.white_bishops ^ 1<<mov.to,
-                Some(Piece::Rook) => self.white_rooks = self.white_rooks ^ 1<<mov.to,
-                Some(Piece::Queen) => self.white_queens = self.white_queens ^ 1<<mov.to,
-                Some(Piece::King) => self.white_king = self.white_king ^ 1<<mov.to,
+                Some(Piece::Pawn) => self.white_pawns ^= mov.to.bitboard(),
+                Some(Piece::Knight) => self.white_knights ^= mov.to.bitboard(),
+                Some(Piece::Bishop) => self.white_bishops ^= mov.to.bitboard(),
+                Some(Piece::Rook) => self.white_rooks ^= mov.to.bitboard(),
+                Some(Piece::Queen) => self.white_queens ^= mov.to.bitboard(),
+                Some(Piece::King) => self.white_king ^= mov.to.bitboard(),
             }
         }
     }
 
 
-    pub fn get_bitboard_by_piece(&self, piece: &Piece, color: &Color) -> u64 {
+    pub fn get_bitboard_by_piece(&self, piece: &Piece, color: &Color) -> Bitboard {
         match color {
             Color::Black => match piece {
                 Piece::Pawn => self.black_pawns,
@@ -462,34 +709,100 @@ impl BitBoard {
         }
     }
 
-    pub fn get_king_by_color(&self, color: &Color) -> u64 {
+    pub fn get_king_by_color(&self, color: &Color) -> Bitboard {
         match color {
             Color::Black => self.black_king,
             Color::White => self.white_king,
         }
     }
 
-    pub fn get_white(&self) -> u64 {
-        self.white_pawns | self.white_knights | self.white_bishops | self.white_rooks | self.white_queens | self.white_king
+    pub fn get_white(&self) -> Bitboard {
+        self.white
     }
 
-    pub fn get_black(&self) -> u64 {
-        self.black_pawns | self.black_knights | self.black_bishops | self.black_rooks | self.black_queens | self.black_king
+    pub fn get_black(&self) -> Bitboard {
+        self.black
     }
 }
 
+fn get_castling_rook_squares(mov: &Move) -> (Square, Square) {
+    let rank = mov.from.rank();
+    match mov.to.file() > mov.from.file() {
+        true => (Square::new(File::H, rank), Square::new(File::F, rank)),
+        false => (Square::new(File::A, rank), Square::new(File::D, rank)),
+    }
+}
+
+// pawn taken by an enpassant capture stands next to the capturing pawn
+fn get_enpassant_capture(mov: &Move) -> Square {
+    Square::new(mov.to.file(), mov.from.rank())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub time: Option<Duration>,
+    pub infinite: bool,
+    pub stop: Option<Arc<AtomicBool>>,
+    // halfmove clock of the searched position and repetition hashes
+    // of the positions that came before it in the game, oldest first
+    pub halfmoves: usize,
+    pub history: Vec<u64>,
+}
+
+// time for the next move in milliseconds, from the remaining time and increment
+pub fn get_move_time(clock: u64, increment: u64, moves_to_go: Option<u64>) -> u64 {
+    let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let budget = clock / moves_to_go + increment / 2;
+    budget.min(clock.saturating_sub(MOVE_OVERHEAD)).max(1)
+}
+
+pub struct SearchInfo {
+    pub depth: u8,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub best_move: Move,
+}
+
 pub trait Engine {
     fn get_name(&self) -> String;
-    fn get_move(&mut self, board: &mut BitBoard, color: &Color) -> Move;
+    // there is no move to return when the side to move is mated or stalemated
+    fn get_move(&mut self, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Option<Move>;
+
+    // engines without iterative search ignore the limits and don't report progress
+    fn search(&mut self, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability, _limits: &SearchLimits, _info: &mut dyn FnMut(&SearchInfo)) -> Option<Move> {
+        self.get_move(board, color, enpassant, castling)
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EngineType {
     Random,
+    AlphaBeta,
+}
+
+impl FromStr for EngineType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<EngineType, String> {
+        match name {
+            "Random" => Ok(EngineType::Random),
+            "AlphaBeta" => Ok(EngineType::AlphaBeta),
+            _ => Err(format!("Unknown engine type: {}", name)),
+        }
+    }
 }
 
 pub fn get_engine(engine: EngineType) -> Box<dyn Engine> {
+    get_engine_with_options(engine, ALPHA_BETA_DEPTH, TRANSPOSITION_TABLE_SIZE)
+}
+
+// depth is searched when no other limit is given, table size is in megabytes
+pub fn get_engine_with_options(engine: EngineType, depth: u8, table_size: usize) -> Box<dyn Engine> {
     match engine {
         EngineType::Random => Box::new(random_engine::RandomEngine::new()),
+        EngineType::AlphaBeta => Box::new(alpha_beta_engine::AlphaBetaEngine::new(depth, table_size)),
     }
 }
 
@@ -497,6 +810,10 @@ pub fn get_engine(engine: EngineType) -> Box<dyn Engine> {
 mod tests {
     use super::*;
 
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
     #[test]
     fn test_generate_bit_board() {
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
@@ -522,7 +839,120 @@ mod tests {
         let fen_obj = generate_bit_board(&fen);
         assert!(fen_obj.is_err());
         let err = fen_obj.err().unwrap();
-        assert!(err.contains("Incorrect color"));
+        assert_eq!(err, FenError::InvalidValue { field: FenField::Color, offset: 44 });
+        assert!(err.to_string().contains("Incorrect color"));
+    }
+
+    #[test]
+    fn test_generate_bit_board_unknown_piece() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKXNR w KQkq - 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidPiece { piece: 'X', offset: 40 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_short_rank() {
+        let fen = "rnbqkbnr/pppppppp/8/8/7/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidRankLength { rank: 4, squares: 7, offset: 23 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_long_rank() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidRankLength { rank: 1, squares: 9, offset: 43 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_wrong_rank_count() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidRankCount { ranks: 7, offset: 41 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_malformed_enpassant() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidValue { field: FenField::Enpassant, offset: 51 }));
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidValue { field: FenField::Enpassant, offset: 52 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_malformed_castling() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkK - 0 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidValue { field: FenField::Castling, offset: 49 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_malformed_clocks() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidValue { field: FenField::Halfmoves, offset: 53 }));
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::InvalidValue { field: FenField::Moves, offset: 55 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_missing_field() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::MissingField { field: FenField::Halfmoves, offset: 52 }));
+    }
+
+    #[test]
+    fn test_generate_bit_board_too_many_fields() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 1".to_string();
+        let err = generate_bit_board(&fen).err();
+        assert_eq!(err, Some(FenError::TooManyFields { offset: 57 }));
+    }
+
+    fn validate(fen: &str) -> Result<(), FenError> {
+        generate_bit_board(fen).unwrap().validate()
+    }
+
+    #[test]
+    fn test_validate_correct_positions() {
+        assert_eq!(validate("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), Ok(()));
+        assert_eq!(validate("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"), Ok(()));
+        assert_eq!(validate("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_king_count() {
+        assert_eq!(validate("8/8/8/8/8/8/8/8 w - - 0 1"), Err(FenError::InvalidKingCount { color: Color::White, count: 0 }));
+        assert_eq!(validate("k7/8/8/8/8/8/8/K6K w - - 0 1"), Err(FenError::InvalidKingCount { color: Color::White, count: 2 }));
+        assert_eq!(validate("8/8/8/8/8/8/8/K7 w - - 0 1"), Err(FenError::InvalidKingCount { color: Color::Black, count: 0 }));
+    }
+
+    #[test]
+    fn test_validate_pawn_on_back_rank() {
+        assert_eq!(validate("k6P/8/8/8/8/8/8/K7 w - - 0 1"), Err(FenError::PawnOnBackRank { square: square("h8") }));
+        assert_eq!(validate("k7/8/8/8/8/8/8/K2p4 w - - 0 1"), Err(FenError::PawnOnBackRank { square: square("d1") }));
+    }
+
+    #[test]
+    fn test_validate_enpassant() {
+        assert_eq!(validate("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1"), Err(FenError::InconsistentEnpassant { square: square("e3") }));
+        assert_eq!(validate("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1"), Err(FenError::InconsistentEnpassant { square: square("e3") }));
+    }
+
+    #[test]
+    fn test_validate_castling() {
+        assert_eq!(validate("r3k2r/8/8/8/8/8/8/R3K1R1 w KQkq - 0 1"), Err(FenError::InconsistentCastling { right: 'K' }));
+        assert_eq!(validate("r3k2r/8/8/8/8/8/8/R4K1R w Q - 0 1"), Err(FenError::InconsistentCastling { right: 'Q' }));
+        assert_eq!(validate("1r2k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), Err(FenError::InconsistentCastling { right: 'q' }));
+    }
+
+    #[test]
+    fn test_validate_opponent_in_check() {
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4KR2 b - - 0 1"), Ok(()));
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), Err(FenError::OpponentInCheck));
     }
 
     #[test]
@@ -545,7 +975,7 @@ mod tests {
         let fen_obj = generate_bit_board(&fen).unwrap();
         assert!(fen_obj.enpassant.is_some());
         let enpassant = fen_obj.enpassant.unwrap();
-        assert_eq!(enpassant, 19);
+        assert_eq!(enpassant, square("e3"));
     }
 
     #[test]
@@ -601,7 +1031,7 @@ mod tests {
     fn test_apply_move_no_capture() {
         let fen = "8/8/8/8/8/8/P7/R3K2R w KQ - 0 1".to_string();
         let mut fen_obj = generate_bit_board(&fen).unwrap();
-        let mov = Move { from: 7, to: 4, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("a1"), to: square("d1"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         fen_obj.board.apply_move(&mov, &Color::White);
         assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/3RK2R");
     }
@@ -610,9 +1040,9 @@ mod tests {
     fn test_unmove_no_capture() {
         let fen = "8/8/8/8/8/8/P7/R3K2R w KQ - 0 1".to_string();
         let mut fen_obj = generate_bit_board(&fen).unwrap();
-        let mov = Move { from: 7, to: 4, piece: Piece::Rook, capture: None, promotion: None, castling: false };
-        fen_obj.board.apply_move(&mov, &Color::White);
+        let mov = Move { from: square("a1"), to: square("d1"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         fen_obj.board.apply_move(&mov, &Color::White);
+        fen_obj.board.undo_move(&mov, &Color::White);
         assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/R3K2R");
     }
 
@@ -620,7 +1050,7 @@ mod tests {
     fn test_apply_move_capture() {
         let fen = "8/8/8/8/8/8/P7/R2rK2R w KQ - 0 1".to_string();
         let mut fen_obj = generate_bit_board(&fen).unwrap();
-        let mov = Move { from: 7, to: 4, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("a1"), to: square("d1"), piece: Piece::Rook, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         fen_obj.board.apply_move(&mov, &Color::White);
         assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/3RK2R");
     }
@@ -629,12 +1059,59 @@ mod tests {
     fn test_unmove_capture() {
         let fen = "8/8/8/8/8/8/P7/R2rK2R w KQ - 0 1".to_string();
         let mut fen_obj = generate_bit_board(&fen).unwrap();
-        let mov = Move { from: 7, to: 4, piece: Piece::Rook, capture: None, promotion: None, castling: false };
-        fen_obj.board.apply_move(&mov, &Color::White);
+        let mov = Move { from: square("a1"), to: square("d1"), piece: Piece::Rook, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         fen_obj.board.apply_move(&mov, &Color::White);
+        fen_obj.board.undo_move(&mov, &Color::White);
         assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/R2rK2R");
     }
 
+    #[test]
+    fn test_apply_move_enpassant() {
+        let fen = "8/8/8/3pP3/8/8/8/8 w - d6 0 1".to_string();
+        let mut fen_obj = generate_bit_board(&fen).unwrap();
+        let mov = Move { from: square("e5"), to: square("d6"), piece: Piece::Pawn, capture: Some(Piece::Pawn), promotion: None, castling: false, enpassant: true };
+        fen_obj.board.apply_move(&mov, &Color::White);
+        assert_eq!(fen_obj.board.to_fen(), "8/8/3P4/8/8/8/8/8");
+    }
+
+    #[test]
+    fn test_unmove_enpassant() {
+        let fen = "8/8/8/8/3Pp3/8/8/8 b - d3 0 1".to_string();
+        let mut fen_obj = generate_bit_board(&fen).unwrap();
+        let mov = Move { from: square("e4"), to: square("d3"), piece: Piece::Pawn, capture: Some(Piece::Pawn), promotion: None, castling: false, enpassant: true };
+        fen_obj.board.apply_move(&mov, &Color::Black);
+        fen_obj.board.undo_move(&mov, &Color::Black);
+        assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/3Pp3/8/8/8");
+    }
+
+    #[test]
+    fn test_apply_move_kingside_castling() {
+        let fen = "8/8/8/8/8/8/P7/R3K2R w KQ - 0 1".to_string();
+        let mut fen_obj = generate_bit_board(&fen).unwrap();
+        let mov = Move { from: square("e1"), to: square("g1"), piece: Piece::King, capture: None, promotion: None, castling: true, enpassant: false };
+        fen_obj.board.apply_move(&mov, &Color::White);
+        assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/R4RK1");
+    }
+
+    #[test]
+    fn test_apply_move_queenside_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/8 b kq - 0 1".to_string();
+        let mut fen_obj = generate_bit_board(&fen).unwrap();
+        let mov = Move { from: square("e8"), to: square("c8"), piece: Piece::King, capture: None, promotion: None, castling: true, enpassant: false };
+        fen_obj.board.apply_move(&mov, &Color::Black);
+        assert_eq!(fen_obj.board.to_fen(), "2kr3r/8/8/8/8/8/8/8");
+    }
+
+    #[test]
+    fn test_unmove_castling() {
+        let fen = "8/8/8/8/8/8/P7/R3K2R w KQ - 0 1".to_string();
+        let mut fen_obj = generate_bit_board(&fen).unwrap();
+        let mov = Move { from: square("e1"), to: square("c1"), piece: Piece::King, capture: None, promotion: None, castling: true, enpassant: false };
+        fen_obj.board.apply_move(&mov, &Color::White);
+        fen_obj.board.undo_move(&mov, &Color::White);
+        assert_eq!(fen_obj.board.to_fen(), "8/8/8/8/8/8/P7/R3K2R");
+    }
+
     #[test]
     fn test_castling_availability_white_king_moves() {
         let castling = CastlingAvailability {
@@ -643,7 +1120,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 3, to: 4, piece: Piece::King, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("e1"), to: square("d1"), piece: Piece::King, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "kq");
     }
@@ -656,7 +1133,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 59, to: 60, piece: Piece::King, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("e8"), to: square("d8"), piece: Piece::King, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "KQ");
     }
@@ -669,7 +1146,7 @@ mod tests {
             white_queenside: false,
             white_kingside: false,
         };
-        let mov = Move { from: 59, to: 60, piece: Piece::King, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("e8"), to: square("d8"), piece: Piece::King, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "-");
     }
@@ -682,7 +1159,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 0, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("h1"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "Qkq");
     }
@@ -695,7 +1172,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 7, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("a1"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "Kkq");
     }
@@ -708,7 +1185,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 5, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("c1"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "KQkq");
     }
@@ -722,7 +1199,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 56, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("h8"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "KQq");
     }
@@ -735,7 +1212,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 63, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("a8"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "KQk");
     }
@@ -748,7 +1225,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 5, to: 60, piece: Piece::Rook, capture: None, promotion: None, castling: false };
+        let mov = Move { from: square("c1"), to: square("d8"), piece: Piece::Rook, capture: None, promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "KQkq");
     }
@@ -761,7 +1238,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 60, to: 0, piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false };
+        let mov = Move { from: square("d8"), to: square("h1"), piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "Qkq");
     }
@@ -774,7 +1251,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 60, to: 7, piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false };
+        let mov = Move { from: square("d8"), to: square("a1"), piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::Black);
         assert_eq!(updated_castling.to_fen(), "Kkq");
     }
@@ -787,7 +1264,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 60, to: 56, piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false };
+        let mov = Move { from: square("d8"), to: square("h8"), piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "KQq");
     }
@@ -800,7 +1277,7 @@ mod tests {
             white_queenside: true,
             white_kingside: true,
         };
-        let mov = Move { from: 60, to: 63, piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false };
+        let mov = Move { from: square("d8"), to: square("a8"), piece: Piece::Queen, capture: Some(Piece::Rook), promotion: None, castling: false, enpassant: false };
         let updated_castling = castling.after_move(&mov, &Color::White);
         assert_eq!(updated_castling.to_fen(), "KQk");
     }
@@ -809,40 +1286,40 @@ mod tests {
     fn test_check_capture_white() {
         let fen = "8/8/8/8/8/8/8/R3K3 w Q - 0 1".to_string();
         let fen_obj = generate_bit_board(&fen).unwrap();
-        let target = 1 << 7;
-        assert_eq!(fen_obj.board.check_capture(&target, &Color::Black), Some(Piece::Rook));
+        let target = square("a1");
+        assert_eq!(fen_obj.board.check_capture(target, &Color::Black), Some(Piece::Rook));
     }
 
     #[test]
     fn test_check_capture_white_no_capture() {
         let fen = "8/8/8/8/8/8/8/R3K3 w Q - 0 1".to_string();
         let fen_obj = generate_bit_board(&fen).unwrap();
-        let target = 1 << 6;
-        assert_eq!(fen_obj.board.check_capture(&target, &Color::Black), None);
+        let target = square("b1");
+        assert_eq!(fen_obj.board.check_capture(target, &Color::Black), None);
     }
 
     #[test]
     fn test_check_capture_black() {
         let fen = "8/8/8/8/8/8/8/r3k3 w Q - 0 1".to_string();
         let fen_obj = generate_bit_board(&fen).unwrap();
-        let target = 1 << 7;
-        assert_eq!(fen_obj.board.check_capture(&target, &Color::White), Some(Piece::Rook));
+        let target = square("a1");
+        assert_eq!(fen_obj.board.check_capture(target, &Color::White), Some(Piece::Rook));
     }
 
     #[test]
     fn test_check_capture_black_no_capture() {
         let fen = "8/8/8/8/8/8/8/r3k3 w Q - 0 1".to_string();
         let fen_obj = generate_bit_board(&fen).unwrap();
-        let target = 1 << 6;
-        assert_eq!(fen_obj.board.check_capture(&target, &Color::White), None);
+        let target = square("b1");
+        assert_eq!(fen_obj.board.check_capture(target, &Color::White), None);
     }
 
     #[test]
     fn test_field_to_num() {
-        assert_eq!(field_to_num("a1"), 7);
-        assert_eq!(field_to_num("a8"), 63);
-        assert_eq!(field_to_num("h1"), 0);
-        assert_eq!(field_to_num("h8"), 56);
+        assert_eq!(square("a1").index(), 7);
+        assert_eq!(square("a8").index(), 63);
+        assert_eq!(square("h1").index(), 0);
+        assert_eq!(square("h8").index(), 56);
     }
 
     #[test]
@@ -884,4 +1361,66 @@ mod tests {
         let fen_obj = generate_bit_board(&fen).unwrap();
         assert_eq!(fen_obj.board.get_king_by_color(&Color::Black), 0x0800_0000_0000_0000);
     }
+
+    #[test]
+    fn test_board_equality() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
+        let mut board = generate_bit_board(&fen).unwrap().board;
+        let initial = board;
+        let mov = rules::string_to_move(&board, "Nf3", &Color::White, &None, &CastlingAvailability::default()).unwrap();
+        board.apply_move(&mov, &Color::White);
+        assert_ne!(board, initial);
+        board.undo_move(&mov, &Color::White);
+        assert_eq!(board, initial);
+
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(serde_json::from_str::<BitBoard>(&json).unwrap(), board);
+    }
+
+    fn assert_caches_in_sync(board: &BitBoard) {
+        assert_eq!(*board, BitBoard::from(Pieces::from(*board)));
+    }
+
+    fn check_caches(board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        for mov in rules::get_legal_moves(board, color, enpassant, castling) {
+            let before = *board;
+            board.apply_move(&mov, color);
+            assert_caches_in_sync(board);
+            check_caches(board, &color.opposite(), &mov.enpassant_square(), &castling.after_move(&mov, color), depth - 1);
+            board.undo_move(&mov, color);
+            assert_eq!(*board, before);
+        }
+    }
+
+    #[test]
+    fn test_caches_follow_moves() {
+        // castling, promotions with captures and en passant for both sides
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let fen = generate_bit_board(fen).unwrap();
+            let mut board = fen.board;
+            assert_caches_in_sync(&board);
+            check_caches(&mut board, &fen.color, &fen.enpassant, &fen.castling, 3);
+        }
+    }
+
+    #[test]
+    fn test_piece_at() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string();
+        let board = generate_bit_board(&fen).unwrap().board;
+        assert_eq!(board.piece_at(square("e4")), Some((Color::White, Piece::Pawn)));
+        assert_eq!(board.piece_at(square("e2")), None);
+        assert_eq!(board.piece_at(square("d1")), Some((Color::White, Piece::Queen)));
+        assert_eq!(board.piece_at(square("g8")), Some((Color::Black, Piece::Knight)));
+        assert_eq!(board.piece_at(square("e8")), Some((Color::Black, Piece::King)));
+        assert_eq!(board.get_white(), 0x0000_0000_0800_F7FF);
+        assert_eq!(board.get_black(), 0xFFFF_0000_0000_0000);
+    }
 }
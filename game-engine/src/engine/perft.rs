@@ -0,0 +1,99 @@
+use crate::Color;
+
+use super::{rules::{get_legal_moves, Move}, square::Square, BitBoard, CastlingAvailability};
+
+// number of leaf nodes of the legal move tree, used to verify move generation
+pub fn perft(depth: usize, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = get_legal_moves(board, color, enpassant, castling);
+    // leaves are counted without playing the last moves
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let opp_color = color.opposite();
+    let mut nodes = 0;
+    for mov in moves {
+        board.apply_move(&mov, color);
+        nodes += perft(depth - 1, board, &opp_color, &mov.enpassant_square(), &castling.after_move(&mov, color));
+        board.undo_move(&mov, color);
+    }
+    nodes
+}
+
+// perft split by the first move, for comparing with other engines
+pub fn perft_divide(depth: usize, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Vec<(Move, u64)> {
+    assert!(depth > 0);
+    let opp_color = color.opposite();
+    let mut result = Vec::new();
+    for mov in get_legal_moves(board, color, enpassant, castling) {
+        board.apply_move(&mov, color);
+        let nodes = perft(depth - 1, board, &opp_color, &mov.enpassant_square(), &castling.after_move(&mov, color));
+        board.undo_move(&mov, color);
+        result.push((mov, nodes));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::generate_bit_board;
+
+    use super::*;
+
+    // depths with more nodes are left for the perft binary, as they are slow in debug builds
+    const MAX_NODES: u64 = 200_000;
+
+    // lines are a FEN followed by ";D<depth> <nodes>" entries
+    fn parse_epd_line(line: &str) -> (String, Vec<(usize, u64)>) {
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap().trim().to_string();
+        let depths = fields
+            .map(|field| {
+                let (depth, nodes) = field.trim().split_once(' ').unwrap();
+                (depth.trim_start_matches('D').parse().unwrap(), nodes.parse().unwrap())
+            })
+            .collect();
+        (fen, depths)
+    }
+
+    #[test]
+    fn test_perft_suite() {
+        let suite = include_str!("../../testdata/perft.epd");
+        for line in suite.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (fen, depths) = parse_epd_line(line);
+            let position = generate_bit_board(&fen).unwrap();
+            let mut board = position.board;
+            for (depth, expected) in depths.into_iter().filter(|(_, nodes)| *nodes <= MAX_NODES) {
+                let nodes = perft(depth, &mut board, &position.color, &position.enpassant, &position.castling);
+                assert_eq!(nodes, expected, "perft {} of {}", depth, fen);
+            }
+        }
+    }
+
+    // all depths take about half a minute in release builds: cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn test_full_perft_suite() {
+        let suite = include_str!("../../testdata/perft.epd");
+        for line in suite.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (fen, depths) = parse_epd_line(line);
+            let position = generate_bit_board(&fen).unwrap();
+            let mut board = position.board;
+            for (depth, expected) in depths {
+                let nodes = perft(depth, &mut board, &position.color, &position.enpassant, &position.castling);
+                assert_eq!(nodes, expected, "perft {} of {}", depth, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_perft_divide_sums_to_perft() {
+        let position = generate_bit_board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut board = position.board;
+        let divide = perft_divide(2, &mut board, &position.color, &position.enpassant, &position.castling);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use crate::Color;
+
+use super::{position::Position, rules::{game_state_from_hashes, move_to_string, string_to_move, GameState, Move, MoveParseError}, FenError};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    InvalidTag { offset: usize },
+    UnterminatedComment { offset: usize },
+    UnbalancedVariation { offset: usize },
+    UnexpectedToken { token: String, offset: usize },
+    InvalidFen(FenError),
+    IllegalMove { ply: usize, san: String, reason: MoveParseError },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag { offset } => write!(f, "Invalid tag pair at offset {}", offset),
+            PgnError::UnterminatedComment { offset } => write!(f, "Unterminated comment at offset {}", offset),
+            PgnError::UnbalancedVariation { offset } => write!(f, "Unbalanced variation at offset {}", offset),
+            PgnError::UnexpectedToken { token, offset } => write!(f, "Unexpected token {} at offset {}", token, offset),
+            PgnError::InvalidFen(err) => write!(f, "Invalid starting position: {}", err),
+            PgnError::IllegalMove { ply, san, reason } => write!(f, "Illegal move {} at ply {}: {}", san, ply, reason),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub fen: String,
+    pub moves: Vec<Move>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn to_pgn(&self) -> Result<String, PgnError> {
+        write_pgn(&self.tags, &self.fen, &self.moves)
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Move(String),
+    Result(String),
+}
+
+// comments, NAGs, move numbers, annotation glyphs and variations are skipped,
+// only the main line is returned
+fn tokenize(pgn: &str) -> Result<Vec<(Token, usize)>, PgnError> {
+    let chars: Vec<(usize, char)> = pgn.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    let mut variation_start = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '%' if offset == 0 || pgn[..offset].ends_with('\n') => {
+                while i < chars.len() && chars[i].1 != '\n' {
+                    i += 1;
+                }
+            },
+            ';' => {
+                while i < chars.len() && chars[i].1 != '\n' {
+                    i += 1;
+                }
+            },
+            '{' => {
+                while i < chars.len() && chars[i].1 != '}' {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(PgnError::UnterminatedComment { offset });
+                }
+                i += 1;
+            },
+            '(' => {
+                if depth == 0 {
+                    variation_start = offset;
+                }
+                depth += 1;
+                i += 1;
+            },
+            ')' => {
+                if depth == 0 {
+                    return Err(PgnError::UnbalancedVariation { offset });
+                }
+                depth -= 1;
+                i += 1;
+            },
+            '[' if depth == 0 => {
+                let (tag, next) = parse_tag(&chars, i).ok_or(PgnError::InvalidTag { offset })?;
+                tokens.push((tag, offset));
+                i = next;
+            },
+            '$' => {
+                i += 1;
+                while i < chars.len() && chars[i].1.is_ascii_digit() {
+                    i += 1;
+                }
+            },
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].1.is_whitespace() && !"{}()[];$".contains(chars[i].1) {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(PgnError::UnexpectedToken { token: c.to_string(), offset });
+                }
+                let symbol: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                if depth > 0 {
+                    continue;
+                }
+                if let Some(token) = parse_symbol(&symbol) {
+                    tokens.push((token, offset));
+                }
+            },
+        }
+    }
+    if depth > 0 {
+        return Err(PgnError::UnbalancedVariation { offset: variation_start });
+    }
+    Ok(tokens)
+}
+
+// [Name "Value"], with \" and \\ escapes in the value
+fn parse_tag(chars: &[(usize, char)], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let skip_whitespace = |i: &mut usize| {
+        while *i < chars.len() && chars[*i].1.is_whitespace() {
+            *i += 1;
+        }
+    };
+
+    skip_whitespace(&mut i);
+    let mut name = String::new();
+    while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '_') {
+        name.push(chars[i].1);
+        i += 1;
+    }
+    skip_whitespace(&mut i);
+    if name.is_empty() || chars.get(i)?.1 != '"' {
+        return None;
+    }
+    i += 1;
+
+    let mut value = String::new();
+    loop {
+        match chars.get(i)?.1 {
+            '"' => break,
+            '\\' => {
+                i += 1;
+                value.push(chars.get(i)?.1);
+            },
+            c => value.push(c),
+        }
+        i += 1;
+    }
+    i += 1;
+    skip_whitespace(&mut i);
+    if chars.get(i)?.1 != ']' {
+        return None;
+    }
+    Some((Token::Tag(name, value), i + 1))
+}
+
+fn parse_symbol(symbol: &str) -> Option<Token> {
+    if RESULTS.contains(&symbol) {
+        return Some(Token::Result(symbol.to_string()));
+    }
+    // move numbers can be attached to the move, as in 12.e4 or 12...e5
+    let san = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = match san.starts_with('.') {
+        true => san.trim_start_matches('.'),
+        false => symbol,
+    };
+    let san = san.trim_end_matches(['!', '?']);
+    if san.is_empty() {
+        return None;
+    }
+    Some(Token::Move(san.replace("0-0", "O-O")))
+}
+
+pub fn parse_pgn(pgn: &str) -> Result<PgnGame, PgnError> {
+    let tokens = tokenize(pgn)?;
+    let mut tags = Vec::new();
+    let mut sans = Vec::new();
+    let mut result = None;
+
+    for (token, offset) in tokens {
+        match token {
+            Token::Tag(name, value) if sans.is_empty() && result.is_none() => tags.push((name, value)),
+            Token::Move(san) if result.is_none() => sans.push(san),
+            Token::Result(value) if result.is_none() => result = Some(value),
+            Token::Tag(name, _) => return Err(PgnError::UnexpectedToken { token: format!("[{}", name), offset }),
+            Token::Move(san) => return Err(PgnError::UnexpectedToken { token: san, offset }),
+            Token::Result(value) => return Err(PgnError::UnexpectedToken { token: value, offset }),
+        }
+    }
+
+    let fen = tags
+        .iter()
+        .find(|(name, _)| name == "FEN")
+        .map_or(START_POSITION.to_string(), |(_, value)| value.clone());
+    let mut position = Position::from_fen(&fen).map_err(PgnError::InvalidFen)?;
+
+    let mut moves = Vec::new();
+    for (ply, san) in sans.into_iter().enumerate() {
+        let mov = string_to_move(&position.board, &san, &position.color, &position.enpassant, &position.castling)
+            .map_err(|reason| PgnError::IllegalMove { ply: ply + 1, san, reason })?;
+        position.make_move(&mov);
+        moves.push(mov);
+    }
+
+    let result = result
+        .or_else(|| tags.iter().find(|(name, _)| name == "Result").map(|(_, value)| value.clone()))
+        .unwrap_or(String::from("*"));
+    Ok(PgnGame { tags, fen, moves, result })
+}
+
+pub fn write_pgn(tags: &[(String, String)], fen: &String, moves: &[Move]) -> Result<String, PgnError> {
+    let mut position = Position::from_fen(fen).map_err(PgnError::InvalidFen)?;
+    let mut history = Vec::new();
+    let mut movetext = Vec::new();
+    let mut state = GameState::Normal;
+
+    for (i, mov) in moves.iter().enumerate() {
+        let color = position.color.clone();
+        match (&color, i) {
+            (Color::White, _) => movetext.push(format!("{}.", position.moves)),
+            (Color::Black, 0) => movetext.push(format!("{}...", position.moves)),
+            _ => {},
+        }
+        movetext.push(move_to_string(&position.board, mov, &color));
+        history.push(position.repetition_hash());
+        position.make_move(mov);
+        state = game_state_from_hashes(&position, &history);
+    }
+
+    let tag_result = tags.iter().find(|(name, _)| name == "Result").map(|(_, value)| value.as_str());
+    let result = match state {
+        GameState::Checkmate => match position.color {
+            Color::White => "0-1",
+            Color::Black => "1-0",
+        },
+        GameState::Stalemate | GameState::SeventyFiveMoves | GameState::FivefoldRepetition
+            | GameState::InsufficientMaterial | GameState::DeadPosition => "1/2-1/2",
+        _ => tag_result.filter(|result| RESULTS.contains(result)).unwrap_or("*"),
+    };
+    movetext.push(result.to_string());
+
+    let mut pgn = String::new();
+    for (name, default) in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => result,
+            _ => tags.iter().find(|(tag, _)| tag == name).map_or(default, |(_, value)| value.as_str()),
+        };
+        pgn.push_str(&format_tag(name, value));
+    }
+    if fen != START_POSITION {
+        pgn.push_str(&format_tag("SetUp", "1"));
+        pgn.push_str(&format_tag("FEN", fen));
+    }
+    for (name, value) in tags {
+        let written = SEVEN_TAG_ROSTER.iter().any(|(tag, _)| tag == name) || name == "SetUp" || name == "FEN";
+        if !written {
+            pgn.push_str(&format_tag(name, value));
+        }
+    }
+    pgn.push('\n');
+    pgn.push_str(&wrap(&movetext));
+    pgn.push('\n');
+    Ok(pgn)
+}
+
+fn format_tag(name: &str, value: &str) -> String {
+    format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn wrap(tokens: &[String]) -> String {
+    let mut result = String::new();
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+            result.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            result.push(' ');
+            line_length += 1;
+        }
+        result.push_str(token);
+        line_length += token.len();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::rules::move_to_lan;
+
+    use super::*;
+
+    fn lan(moves: &[Move]) -> Vec<String> {
+        moves.iter().map(move_to_lan).collect()
+    }
+
+    #[test]
+    fn test_parse_tags_and_moves() {
+        let pgn = "[Event \"Casual \\\"blitz\\\"\"]\n[White \"Alice\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0\n";
+        let game = parse_pgn(pgn).unwrap();
+        assert_eq!(game.tags[0], ("Event".to_string(), "Casual \"blitz\"".to_string()));
+        assert_eq!(game.tags[1], ("White".to_string(), "Alice".to_string()));
+        assert_eq!(lan(&game.moves), vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]);
+        assert_eq!(game.result, "1-0");
+    }
+
+    #[test]
+    fn test_parse_comments_nags_and_variations() {
+        let pgn = "1. e4 {best by test} e5 $1 2. Nf3!? (2. f4 exf4 (2... d5) 3. Nf3) Nc6?! ; rest of line\n3.Bc4 3...Bc5 *";
+        let game = parse_pgn(pgn).unwrap();
+        assert_eq!(lan(&game.moves), vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5"]);
+        assert_eq!(game.result, "*");
+    }
+
+    #[test]
+    fn test_parse_from_fen() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1\"]\n\n1... O-O-O 2. 0-0 *";
+        let game = parse_pgn(pgn).unwrap();
+        assert_eq!(lan(&game.moves), vec!["e8c8", "e1g1"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_pgn("[Event Casual]\n1. e4 *").err(), Some(PgnError::InvalidTag { offset: 0 }));
+        assert_eq!(parse_pgn("1. e4 {never closed").err(), Some(PgnError::UnterminatedComment { offset: 6 }));
+        assert_eq!(parse_pgn("1. e4 (1. d4 e5").err(), Some(PgnError::UnbalancedVariation { offset: 6 }));
+        assert_eq!(parse_pgn("1. e4 ) e5").err(), Some(PgnError::UnbalancedVariation { offset: 6 }));
+        assert_eq!(parse_pgn("1. e4 e5 1-0 Nf3").err(), Some(PgnError::UnexpectedToken { token: "Nf3".to_string(), offset: 13 }));
+        let err = parse_pgn("1. e4 e5 2. Ke3 *").err().unwrap();
+        assert!(matches!(err, PgnError::IllegalMove { ply: 3, .. }));
+        let err = parse_pgn("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n*").err().unwrap();
+        assert!(matches!(err, PgnError::InvalidFen(_)));
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let game = parse_pgn("[White \"Alice\"]\n[Black \"Bob\"]\n[Opening \"Fool's mate\"]\n1. f3 e5 2. g4 Qh4# 0-1").unwrap();
+        let pgn = game.to_pgn().unwrap();
+        assert_eq!(pgn, "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"0-1\"]\n[Opening \"Fool's mate\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n");
+    }
+
+    #[test]
+    fn test_write_pgn_from_fen() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1".to_string();
+        let game = parse_pgn(&format!("[FEN \"{}\"]\n1... O-O-O 2. O-O *", fen)).unwrap();
+        let pgn = write_pgn(&[], &fen, &game.moves).unwrap();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1\"]\n"));
+        assert!(pgn.ends_with("\n1... O-O-O 2. O-O *\n"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pgn = "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 *";
+        let game = parse_pgn(pgn).unwrap();
+        let exported = game.to_pgn().unwrap();
+        assert!(exported.lines().all(|line| line.len() <= LINE_LENGTH));
+        let reimported = parse_pgn(&exported).unwrap();
+        assert_eq!(lan(&reimported.moves), lan(&game.moves));
+        assert!(exported.contains("10. d4 Nbd7 *"));
+    }
+
+    #[test]
+    fn test_export_matches_corpus() {
+        let corpus = include_str!("../../testdata/games.pgn");
+        let words = |pgn: &str| pgn.split_whitespace().map(String::from).collect::<Vec<String>>();
+        for pgn in corpus.split("\n\n[").map(|pgn| pgn.trim_start_matches('[')) {
+            let game = parse_pgn(&format!("[{}", pgn)).unwrap();
+            assert_eq!(words(&game.to_pgn().unwrap()), words(&format!("[{}", pgn)));
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::Color;
+
+use super::{generate_bit_board, rules::{get_legal_moves, Move, Piece}, square::Square, zobrist::{get_hash, hash_castling, hash_enpassant, hash_move}, BitBoard, CastlingAvailability, FenError};
+
+#[derive(Clone)]
+pub struct Position {
+    pub board: BitBoard,
+    pub color: Color,
+    pub castling: CastlingAvailability,
+    pub enpassant: Option<Square>,
+    pub halfmoves: usize,
+    pub moves: usize,
+    pub hash: u64,
+}
+
+// state that cannot be recovered from the move itself
+pub struct Undo {
+    castling: CastlingAvailability,
+    enpassant: Option<Square>,
+    halfmoves: usize,
+}
+
+impl Position {
+    pub fn new(board: BitBoard, color: Color, castling: CastlingAvailability, enpassant: Option<Square>, halfmoves: usize, moves: usize) -> Position {
+        let hash = get_hash(&board, &color, &castling, &enpassant);
+        Position {
+            board,
+            color,
+            castling,
+            enpassant,
+            halfmoves,
+            moves,
+            hash,
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
+        let fen = generate_bit_board(fen)?;
+        fen.validate()?;
+        Ok(Position::new(fen.board, fen.color, fen.castling, fen.enpassant, fen.halfmoves, fen.moves))
+    }
+
+    pub fn make_move(&mut self, mov: &Move) -> Undo {
+        let undo = Undo {
+            castling: self.castling,
+            enpassant: self.enpassant,
+            halfmoves: self.halfmoves,
+        };
+
+        self.hash ^= hash_move(mov, &self.color) ^ hash_castling(&self.castling) ^ hash_enpassant(&self.enpassant);
+        self.board.apply_move(mov, &self.color);
+        self.castling = self.castling.after_move(mov, &self.color);
+        self.enpassant = mov.enpassant_square();
+        self.hash ^= hash_castling(&self.castling) ^ hash_enpassant(&self.enpassant);
+        self.halfmoves = match (mov.piece, mov.capture) {
+            (Piece::Pawn, _) => 0,
+            (_, Some(_)) => 0,
+            _ => self.halfmoves + 1,
+        };
+        if self.color == Color::Black {
+            self.moves += 1;
+        }
+        self.color = self.color.opposite();
+
+        undo
+    }
+
+    pub fn unmake_move(&mut self, mov: &Move, undo: Undo) {
+        self.color = self.color.opposite();
+        if self.color == Color::Black {
+            self.moves -= 1;
+        }
+        self.board.undo_move(mov, &self.color);
+        self.hash ^= hash_castling(&self.castling) ^ hash_enpassant(&self.enpassant);
+        self.castling = undo.castling;
+        self.enpassant = undo.enpassant;
+        self.halfmoves = undo.halfmoves;
+        self.hash ^= hash_move(mov, &self.color) ^ hash_castling(&self.castling) ^ hash_enpassant(&self.enpassant);
+    }
+
+    // positions are repeated if the pieces, side to move, castling rights
+    // and possible enpassant captures are the same, so the enpassant square
+    // is hashed out when no pawn can capture there
+    pub fn repetition_hash(&self) -> u64 {
+        match self.has_enpassant_capture() {
+            true => self.hash,
+            false => self.hash ^ hash_enpassant(&self.enpassant),
+        }
+    }
+
+    fn has_enpassant_capture(&self) -> bool {
+        if self.enpassant.is_none() {
+            return false;
+        }
+        get_legal_moves(&self.board, &self.color, &self.enpassant, &CastlingAvailability::default())
+            .iter()
+            .any(|mov| mov.enpassant)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let board = self.board.to_fen();
+        let color = self.color.to_fen();
+        let castling = self.castling.to_fen();
+        let enpassant = self.enpassant.map_or(String::from("-"), |square| square.to_string());
+        let halfmoves = self.halfmoves;
+        let moves = self.moves;
+        format!("{board} {color} {castling} {enpassant} {halfmoves} {moves}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::rules::{get_possible_moves, string_to_move};
+
+    use super::*;
+
+    fn make_moves(position: &mut Position, moves: &[&str]) {
+        for mov in moves {
+            let mov = string_to_move(&position.board, mov, &position.color, &position.enpassant, &position.castling).unwrap();
+            position.make_move(&mov);
+        }
+    }
+
+    #[test]
+    fn test_to_fen() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 3 17".to_string();
+        let position = Position::from_fen(&fen).unwrap();
+        assert_eq!(position.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_from_fen_validates_position() {
+        let result = Position::from_fen("8/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert_eq!(result.err(), Some(FenError::InvalidKingCount { color: Color::Black, count: 0 }));
+    }
+
+    #[test]
+    fn test_make_move_double_push() {
+        let mut position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        make_moves(&mut position, &["e4"]);
+        assert_eq!(position.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        make_moves(&mut position, &["c5"]);
+        assert_eq!(position.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+    }
+
+    #[test]
+    fn test_make_move_clocks() {
+        let mut position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        make_moves(&mut position, &["Nf3", "Nc6", "Ng1"]);
+        assert_eq!(position.to_fen(), "r1bqkbnr/pppppppp/2n5/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 3 2");
+        make_moves(&mut position, &["e5"]);
+        assert_eq!(position.to_fen(), "r1bqkbnr/pppp1ppp/2n5/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 3");
+    }
+
+    #[test]
+    fn test_make_move_castling() {
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        make_moves(&mut position, &["O-O", "Rh1+"]);
+        assert_eq!(position.to_fen(), "r3k3/8/8/8/8/8/8/R4RKr w q - 2 2");
+    }
+
+    #[test]
+    fn test_repetition_hash_ignores_impossible_enpassant() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let without = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(position.repetition_hash(), without.repetition_hash());
+        let position = Position::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3").unwrap();
+        let without = Position::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3").unwrap();
+        assert_ne!(position.repetition_hash(), without.repetition_hash());
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 4 3".to_string();
+        let mut position = Position::from_fen(&fen).unwrap();
+        let mov = string_to_move(&position.board, "exf6", &position.color, &position.enpassant, &position.castling).unwrap();
+        let undo = position.make_move(&mov);
+        assert_eq!(position.to_fen(), "rnbqkbnr/ppp1p1pp/5P2/3p4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3");
+        position.unmake_move(&mov, undo);
+        assert_eq!(position.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_unmake_all_moves() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 20".to_string();
+        let mut position = Position::from_fen(&fen).unwrap();
+        let moves = get_possible_moves(&position.board, &position.color, &position.enpassant, &position.castling);
+        for mov in moves {
+            let undo = position.make_move(&mov);
+            let hash = get_hash(&position.board, &position.color, &position.castling, &position.enpassant);
+            assert_eq!(position.hash, hash);
+            position.unmake_move(&mov, undo);
+            assert_eq!(position.to_fen(), fen);
+            assert_eq!(position.hash, get_hash(&position.board, &position.color, &position.castling, &position.enpassant));
+        }
+    }
+}
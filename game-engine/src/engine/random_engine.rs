@@ -2,7 +2,7 @@ use rand::{rngs::ThreadRng, thread_rng, Rng};
 
 use crate::Color;
 
-use super::{rules::{get_capture_map, get_possible_moves, Move}, BitBoard, Engine};
+use super::{rules::{get_legal_moves, Move}, square::Square, BitBoard, CastlingAvailability, Engine};
 
 pub struct RandomEngine {
     rng: ThreadRng,
@@ -21,20 +21,13 @@ impl Engine for RandomEngine {
         String::from("Random Engine")
     }
 
-    fn get_move(&mut self, board: &mut BitBoard, color: &Color) -> Move {
-        let moves = get_possible_moves(board, color);
-        let opp_color = color.opposite();
-
-        let mut moves: Vec<Move> = moves.into_iter().filter(|mv| {
-            board.apply_move(mv, color);
-            let captures = get_capture_map(board, &opp_color);
-            let king = board.get_king_by_color(color);
-            let no_check = king & captures == 0;
-            board.apply_move(mv, color);
-            no_check
-        }).collect();
+    fn get_move(&mut self, board: &mut BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Option<Move> {
+        let mut moves = get_legal_moves(board, color, enpassant, castling);
+        if moves.is_empty() {
+            return None;
+        }
 
         let index = self.rng.gen_range(0..moves.len());
-        moves.swap_remove(index)
+        Some(moves.swap_remove(index))
     }
 }
@@ -1,550 +1,542 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::Color;
 
-use super::BitBoard;
-
-const NOT_A_FILE: u64 =  0b01111111_01111111_01111111_01111111_01111111_01111111_01111111_01111111;
-const NOT_AB_FILE: u64 = 0b00111111_00111111_00111111_00111111_00111111_00111111_00111111_00111111;
-const NOT_H_FILE: u64 =  0b11111110_11111110_11111110_11111110_11111110_11111110_11111110_11111110;
-const NOT_GH_FILE: u64 = 0b11111100_11111100_11111100_11111100_11111100_11111100_11111100_11111100;
-const RANK_4: u64 =      0b00000000_00000000_00000000_00000000_11111111_00000000_00000000_00000000;
-const RANK_5: u64 =      0b00000000_00000000_00000000_11111111_00000000_00000000_00000000_00000000;
-const WHITE_PROMOTION: u64 =      0b11111111_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-const BLACK_PROMOTION: u64 =      0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_11111111;
-
-
-const NORTH: usize = 0;
-const EAST: usize = 1;
-const SOUTH: usize = 2;
-const WEST: usize = 3;
-
-const NORTHWEST: usize = 4;
-const NORTHEAST: usize = 5;
-const SOUTHEAST: usize = 6;
-const SOUTHWEST: usize = 7;
-
-pub static ROOK_RAYS: Lazy<[[u64; 64]; 4]> = Lazy::new(|| {
-    let mut rays = [[0u64; 64]; 4];
-
-    for square in 0..64 {
-        rays[NORTH][square] = generate_ray(square, NORTH);
-        rays[EAST][square] = generate_ray(square, EAST);
-        rays[SOUTH][square] = generate_ray(square, SOUTH);
-        rays[WEST][square] = generate_ray(square, WEST);
-    }
-
-    rays
-});
-
-pub static BISHOP_RAYS: Lazy<[[u64; 64]; 4]> = Lazy::new(|| {
-    let mut rays = [[0u64; 64]; 4];
-
-    for square in 0..64 {
-        rays[NORTHWEST-4][square] = generate_ray(square, NORTHWEST);
-        rays[NORTHEAST-4][square] = generate_ray(square, NORTHEAST);
-        rays[SOUTHEAST-4][square] = generate_ray(square, SOUTHEAST);
-        rays[SOUTHWEST-4][square] = generate_ray(square, SOUTHWEST);
-    }
-
-    rays
-});
-
-fn generate_ray(square: usize, direction: usize) -> u64 {
-    let mut ray: u64 = 0;
-    let mut sq: u64 = 1 << square;
-    while sq != 0 {
-        sq = match direction {
-            NORTH => sq << 8,
-            SOUTH => sq >> 8,
-            EAST => sq << 1 & NOT_H_FILE,
-            WEST => sq >> 1 & NOT_A_FILE,
-            NORTHWEST => sq << 7 & NOT_A_FILE,
-            NORTHEAST => sq << 9 & NOT_H_FILE,
-            SOUTHEAST => sq >> 7 & NOT_H_FILE,
-            SOUTHWEST => sq >> 9 & NOT_A_FILE,
-            _ => panic!("error while constructing ray"),
-        };
-        ray = ray | sq;
-    }
-    ray
-}
+use super::{magic::{get_bishop_attacks, get_rook_attacks}, position::Position, square::{Bitboard, Direction, File, Rank, Square}, BitBoard, CastlingAvailability, FenError};
+
+const DARK_SQUARES: Bitboard = Bitboard(0x55AA_55AA_55AA_55AA);
 
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Piece {
     Pawn, Knight, Rook, Bishop, Queen, King,
 }
 
+// serialized as {"from":"e2","to":"e4","promotion":null,"piece":"pawn"}, flags with default values are left out;
+// the piece is required, as a move applied with the wrong piece corrupts the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Move {
-    pub from: u8,
-    pub to: u8,
+    pub from: Square,
+    pub to: Square,
     pub promotion: Option<Piece>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub capture: Option<Piece>,
+    #[serde(default, skip_serializing_if = "is_false")]
     pub castling: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub enpassant: bool,
     pub piece: Piece,
 }
 
-pub fn get_possible_moves(board: &BitBoard, color: &Color) -> Vec<Move> {
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Move {
+    pub fn enpassant_square(&self) -> Option<Square> {
+        let ranks = self.to.rank().index() as i8 - self.from.rank().index() as i8;
+        match (self.piece, ranks) {
+            (Piece::Pawn, 2 | -2) => self.from.offset(0, ranks / 2),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_possible_moves(board: &BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Vec<Move> {
     let (pawns, knights, bishops, rooks, queens, king, enemy, promotion_rank) = match color {
         Color::Black => (board.black_pawns, board.black_knights, board.black_bishops, board.black_rooks, board.black_queens, board.black_king, 
-                       board.get_white(), BLACK_PROMOTION), 
+                       board.get_white(), Rank::FIRST.bitboard()), 
         Color::White => (board.white_pawns, board.white_knights, board.white_bishops, board.white_rooks, board.white_queens, board.white_king,
-                       board.get_black(), WHITE_PROMOTION),
+                       board.get_black(), Rank::EIGHTH.bitboard()),
     };
     let mut result = Vec::new();
-    let mut current = knights;
     let friendly = pawns | knights | bishops | rooks | queens | king;
     let all_pieces = friendly | enemy;
     let empty = !all_pieces;
-    while current != 0 {
-        let from = current.trailing_zeros() as u8;
-        let knight = 1 << from;
-        current = current & !knight;
-        let mut moves = get_knight_moves(&knight, &(empty | enemy));
-        while moves != 0 {
-            let to = moves.trailing_zeros() as u8;
-            let target = 1 << to;
-            moves = moves & !target;
-            let capture = board.check_capture(&target, color);
-            result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::Knight });
+    let enpassant_target = get_enpassant_target(board, color, enpassant);
+    for from in knights {
+        for to in get_knight_moves(&from.bitboard(), &(empty | enemy)) {
+            let capture = board.check_capture(to, color);
+            result.push(Move { from, to, promotion: None, capture, castling: false, enpassant: false, piece: Piece::Knight });
         }
     }
 
-    current = rooks | queens;
-    while current != 0 {
-        let from = current.trailing_zeros() as u8;
-        let rook = 1 << from;
-        let piece = match rook & rooks != 0 {
+    for from in rooks | queens {
+        let piece = match rooks.contains(from) {
             true => Piece::Rook,
             false => Piece::Queen,
         };
-        current = current & !rook;
-        let mut moves = get_rook_moves(&rook, &all_pieces, &friendly);
-        while moves != 0 {
-            let to = moves.trailing_zeros() as u8;
-            let target = 1 << to;
-            moves = moves & !target;
-            let capture = board.check_capture(&target, color);
-            result.push(Move { from, to, promotion: None, capture, castling: false, piece });
+        for to in get_rook_moves(from, &all_pieces, &friendly) {
+            let capture = board.check_capture(to, color);
+            result.push(Move { from, to, promotion: None, capture, castling: false, enpassant: false, piece });
         }
     }
 
-    current = bishops | queens;
-    while current != 0 {
-        let from = current.trailing_zeros() as u8;
-        let bishop = 1 << from;
-        let piece = match bishop & bishops != 0 {
+    for from in bishops | queens {
+        let piece = match bishops.contains(from) {
             true => Piece::Bishop,
             false => Piece::Queen,
         };
-        current = current & !bishop;
-        let mut moves = get_bishop_moves(&bishop, &all_pieces, &friendly);
-        while moves != 0 {
-            let to = moves.trailing_zeros() as u8;
-            let target = 1 << to;
-            moves = moves & !target;
-            let capture = board.check_capture(&target, color);
-            result.push(Move { from, to, promotion: None, capture, castling: false, piece });
+        for to in get_bishop_moves(from, &all_pieces, &friendly) {
+            let capture = board.check_capture(to, color);
+            result.push(Move { from, to, promotion: None, capture, castling: false, enpassant: false, piece });
         }
     }
 
-    let from = king.trailing_zeros() as u8;
-    let mut moves = get_king_moves(&king, &(empty | enemy));
-    while moves != 0 {
-        let to = moves.trailing_zeros() as u8;
-        let target = 1 << to;
-        moves = moves & !target;
-        let capture = board.check_capture(&target, color);
-        result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::King });
-    }
-
-    if color == &Color::White {
-        let mut single = get_white_pawn_single_pushes(&pawns, &empty);
-        while single != 0 {
-            let to = single.trailing_zeros() as u8;
-            let from = to - 8;
-            let pawn = 1 << to;
-            single = single & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture: None, castling: false, piece: Piece::Pawn }); // TODO: promotion
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture: None, castling: false, piece: Piece::Pawn });
-            }
+    if let Some(from) = king.first() {
+        for to in get_king_moves(&king, &(empty | enemy)) {
+            let capture = board.check_capture(to, color);
+            result.push(Move { from, to, promotion: None, capture, castling: false, enpassant: false, piece: Piece::King });
         }
+    }
 
-        let mut double = get_white_pawn_double_pushes(&pawns, &empty);
-        while double != 0 {
-            let to = double.trailing_zeros() as u8;
-            let from = to - 16;
-            let pawn = 1 << to;
-            double = double & !pawn;
-            result.push(Move { from, to, promotion: None, capture: None, castling: false, piece: Piece::Pawn });
+    // targets of each kind of pawn move, with the offset back to the pawn
+    let (forward, single, double, east_enpassant, west_enpassant, east_captures, west_captures) = match color {
+        Color::White => (1, get_white_pawn_single_pushes(&pawns, &empty), get_white_pawn_double_pushes(&pawns, &empty),
+                       get_white_pawn_east_attacks(&pawns, &enpassant_target), get_white_pawn_west_attacks(&pawns, &enpassant_target),
+                       get_white_pawn_east_attacks(&pawns, &enemy), get_white_pawn_west_attacks(&pawns, &enemy)),
+        Color::Black => (-1, get_black_pawn_single_pushes(&pawns, &empty), get_black_pawn_double_pushes(&pawns, &empty),
+                       get_black_pawn_east_attacks(&pawns, &enpassant_target), get_black_pawn_west_attacks(&pawns, &enpassant_target),
+                       get_black_pawn_east_attacks(&pawns, &enemy), get_black_pawn_west_attacks(&pawns, &enemy)),
+    };
+    add_pawn_moves(&mut result, board, color, single, (0, -forward), &promotion_rank);
+    add_pawn_moves(&mut result, board, color, double, (0, -2 * forward), &promotion_rank);
+    for (targets, files) in [(east_enpassant, 1), (west_enpassant, -1)] {
+        for to in targets {
+            let Some(from) = to.offset(files, -forward) else {
+                continue;
+            };
+            result.push(Move { from, to, promotion: None, capture: Some(Piece::Pawn), castling: false, enpassant: true, piece: Piece::Pawn });
         }
+    }
+    add_pawn_moves(&mut result, board, color, east_captures, (1, -forward), &promotion_rank);
+    add_pawn_moves(&mut result, board, color, west_captures, (-1, -forward), &promotion_rank);
+    result.append(&mut get_castling_moves(board, color, castling));
 
-        // TODO: enpassant
-        let mut east_captures = get_white_pawn_east_attacks(&pawns, &enemy);
-        while east_captures != 0 {
-            let to = east_captures.trailing_zeros() as u8;
-            let from = to - 9;
-            let pawn = 1 << to;
-            let capture = board.check_capture(&pawn, color);
-            east_captures = east_captures & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::Pawn });
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture, castling: false, piece: Piece::Pawn });
-            }
-        }
+    result
+}
 
-        let mut west_captures = get_white_pawn_west_attacks(&pawns, &enemy);
-        while west_captures != 0 {
-            let to = west_captures.trailing_zeros() as u8;
-            let from = to - 7;
-            let pawn = 1 << to;
-            let capture = board.check_capture(&pawn, color);
-            west_captures = west_captures & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::Pawn });
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture, castling: false, piece: Piece::Pawn });
-            }
-        }
-    } else {
-        let mut single = get_black_pawn_single_pushes(&pawns, &empty);
-        while single != 0 {
-            let to = single.trailing_zeros() as u8;
-            let from = to + 8;
-            let pawn = 1 << to;
-            single = single & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture: None, castling: false, piece: Piece::Pawn });
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture: None, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture: None, castling: false, piece: Piece::Pawn });
+// pawns stand at the offset from the targets, and moves to the last rank are added with every promotion
+fn add_pawn_moves(result: &mut Vec<Move>, board: &BitBoard, color: &Color, targets: Bitboard, (files, ranks): (i8, i8), promotion_rank: &Bitboard) {
+    for to in targets {
+        let Some(from) = to.offset(files, ranks) else {
+            continue;
+        };
+        let capture = board.check_capture(to, color);
+        if !promotion_rank.contains(to) {
+            result.push(Move { from, to, promotion: None, capture, castling: false, enpassant: false, piece: Piece::Pawn });
+        } else {
+            for promotion in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+                result.push(Move { from, to, promotion: Some(promotion), capture, castling: false, enpassant: false, piece: Piece::Pawn });
             }
         }
+    }
+}
 
-        let mut double = get_black_pawn_double_pushes(&pawns, &empty);
-        while double != 0 {
-            let to = double.trailing_zeros() as u8;
-            let from = to + 16;
-            let pawn = 1 << to;
-            double = double & !pawn;
-            result.push(Move { from, to, promotion: None, capture: None, castling: false, piece: Piece::Pawn });
-        }
-
-        // TODO: enpassant
-        let mut east_captures = get_black_pawn_east_attacks(&pawns, &enemy);
-        while east_captures != 0 {
-            let to = east_captures.trailing_zeros() as u8;
-            let from = to + 9;
-            let pawn = 1 << to;
-            let capture = board.check_capture(&pawn, color);
-            east_captures = east_captures & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::Pawn });
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture, castling: false, piece: Piece::Pawn });
-            }
-        }
+pub fn get_castling_moves(board: &BitBoard, color: &Color, castling: &CastlingAvailability) -> Vec<Move> {
+    let (kingside, queenside, king, rooks, rank) = match color {
+        Color::White => (castling.white_kingside, castling.white_queenside, board.white_king, board.white_rooks, Rank::FIRST),
+        Color::Black => (castling.black_kingside, castling.black_queenside, board.black_king, board.black_rooks, Rank::EIGHTH),
+    };
+    let mut result = Vec::new();
+    let from = Square::new(File::E, rank);
+    if !(kingside || queenside) || !king.contains(from) {
+        return result;
+    }
+    let empty = !(board.get_white() | board.get_black());
+    let attacked = get_capture_map(board, &color.opposite());
+    let squares = |files: &[File]| files.iter().fold(Bitboard::EMPTY, |squares, file| squares | Square::new(*file, rank).bitboard());
 
-        let mut west_captures = get_black_pawn_west_attacks(&pawns, &enemy);
-        while west_captures != 0 {
-            let to = west_captures.trailing_zeros() as u8;
-            let from = to + 7;
-            let pawn = 1 << to;
-            let capture = board.check_capture(&pawn, color);
-            west_captures = west_captures & !pawn;
-            if pawn & promotion_rank == 0 {
-                result.push(Move { from, to, promotion: None, capture, castling: false, piece: Piece::Pawn });
-            } else {
-                result.push(Move { from, to, promotion: Some(Piece::Knight), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Bishop), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Rook), capture, castling: false, piece: Piece::Pawn });
-                result.push(Move { from, to, promotion: Some(Piece::Queen), capture, castling: false, piece: Piece::Pawn });
-            }
-        }
+    let kingside_empty = squares(&[File::F, File::G]);
+    let kingside_safe = squares(&[File::E, File::F, File::G]);
+    if kingside && rooks.contains(Square::new(File::H, rank)) && empty & kingside_empty == kingside_empty && (attacked & kingside_safe).is_empty() {
+        result.push(Move { from, to: Square::new(File::G, rank), promotion: None, capture: None, castling: true, enpassant: false, piece: Piece::King });
     }
-    // TODO: castling
 
+    let queenside_empty = squares(&[File::B, File::C, File::D]);
+    let queenside_safe = squares(&[File::C, File::D, File::E]);
+    if queenside && rooks.contains(Square::new(File::A, rank)) && empty & queenside_empty == queenside_empty && (attacked & queenside_safe).is_empty() {
+        result.push(Move { from, to: Square::new(File::C, rank), promotion: None, capture: None, castling: true, enpassant: false, piece: Piece::King });
+    }
 
     result
 }
 
-pub fn get_knight_moves(knight: &u64, targets: &u64) -> u64 {
-    let mut result: u64 = 0;
-    result = result | ((knight << 17) & NOT_H_FILE);
-    result = result | ((knight << 10) & NOT_GH_FILE);
-    result = result | ((knight >> 6) & NOT_GH_FILE);
-    result = result | ((knight >> 15) & NOT_H_FILE);
-    result = result | ((knight << 15) & NOT_A_FILE);
-    result = result | ((knight << 6) & NOT_AB_FILE);
-    result = result | ((knight >> 10) & NOT_AB_FILE);
-    result = result | ((knight >> 17) & NOT_A_FILE);
-    result & targets
+// only legal moves; checking pieces and pins are computed once instead of trying every move
+pub fn get_legal_moves(board: &BitBoard, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Vec<Move> {
+    let moves = get_possible_moves(board, color, enpassant, castling);
+    let king = board.get_king_by_color(color);
+    let Some(king_square) = king.first() else {
+        return moves;
+    };
+    let opp_color = color.opposite();
+    let occupied = board.get_white() | board.get_black();
+
+    let checkers = get_attackers(board, king_square, &opp_color, occupied);
+    // squares that stop a single check; with two checkers only the king can move
+    let evasions = match (checkers.count(), checkers.first()) {
+        (0, _) => Bitboard::FULL,
+        (1, Some(checker)) => checkers | get_between(king_square, checker),
+        _ => Bitboard::EMPTY,
+    };
+    let pins = get_pins(board, king_square, color, occupied);
+
+    moves
+        .into_iter()
+        .filter(|mov| match (mov.piece, mov.castling, mov.enpassant) {
+            // castling moves are generated only if the king is not in check and doesn't pass attacked squares
+            (Piece::King, true, _) => true,
+            // king is removed, so it cannot step back along the ray of a checking piece
+            (Piece::King, false, _) => get_attackers(board, mov.to, &opp_color, occupied ^ king).is_empty(),
+            // enpassant removes two pawns from one rank, which isn't described by pins
+            (_, _, true) => is_legal(&mut board.clone(), mov, color),
+            _ => {
+                let pinned_off_line = pins.iter().any(|(pinned, line)| pinned.contains(mov.from) && !line.contains(mov.to));
+                evasions.contains(mov.to) && !pinned_off_line
+            },
+        })
+        .collect()
 }
 
-pub fn get_king_moves(king: &u64, targets: &u64) -> u64 {
-    let mut result: u64 = 0;
-    result = result | (king << 8);
-    result = result | (king >> 8);
-    result = result | ((king << 1) & NOT_H_FILE);
-    result = result | ((king << 9) & NOT_H_FILE);
-    result = result | ((king >> 7) & NOT_H_FILE);
-    result = result | ((king >> 1) & NOT_A_FILE);
-    result = result | ((king >> 9) & NOT_A_FILE);
-    result = result | ((king << 7) & NOT_A_FILE);
-    result & targets
+// pieces of the given color attacking the square
+fn get_attackers(board: &BitBoard, square: Square, color: &Color, occupied: Bitboard) -> Bitboard {
+    let target = square.bitboard();
+    let (pawns, knights, bishops, rooks, queens, king) = match color {
+        Color::White => (board.white_pawns, board.white_knights, board.white_bishops, board.white_rooks, board.white_queens, board.white_king),
+        Color::Black => (board.black_pawns, board.black_knights, board.black_bishops, board.black_rooks, board.black_queens, board.black_king),
+    };
+    // pawns attacking the square stand where a pawn of the other color on it would attack
+    let pawns = match color {
+        Color::White => get_black_pawn_east_attacks(&target, &pawns) | get_black_pawn_west_attacks(&target, &pawns),
+        Color::Black => get_white_pawn_east_attacks(&target, &pawns) | get_white_pawn_west_attacks(&target, &pawns),
+    };
+    pawns
+        | get_knight_moves(&target, &knights)
+        | get_king_moves(&target, &king)
+        | get_rook_attacks(square, occupied) & (rooks | queens)
+        | get_bishop_attacks(square, occupied) & (bishops | queens)
 }
 
-pub fn get_white_pawn_single_pushes(pawns: &u64, empty: &u64) -> u64 {
-    (pawns << 8) & empty
+// squares strictly between two squares on one line, empty if they are not on one line
+fn get_between(from: Square, to: Square) -> Bitboard {
+    let target = to.bitboard();
+    let origin = from.bitboard();
+    if get_rook_attacks(from, Bitboard::EMPTY).contains(to) {
+        return get_rook_attacks(from, target) & get_rook_attacks(to, origin);
+    }
+    if get_bishop_attacks(from, Bitboard::EMPTY).contains(to) {
+        return get_bishop_attacks(from, target) & get_bishop_attacks(to, origin);
+    }
+    Bitboard::EMPTY
 }
 
-pub fn get_black_pawn_single_pushes(pawns: &u64, empty: &u64) -> u64 {
-    (pawns >> 8) & empty
+// pieces pinned to the king, each with the line it can still move along
+fn get_pins(board: &BitBoard, king_square: Square, color: &Color, occupied: Bitboard) -> Vec<(Bitboard, Bitboard)> {
+    let (friendly, rooks, bishops) = match color {
+        Color::White => (board.get_white(), board.black_rooks | board.black_queens, board.black_bishops | board.black_queens),
+        Color::Black => (board.get_black(), board.white_rooks | board.white_queens, board.white_bishops | board.white_queens),
+    };
+    let snipers = get_rook_attacks(king_square, Bitboard::EMPTY) & rooks | get_bishop_attacks(king_square, Bitboard::EMPTY) & bishops;
+    let mut pins = Vec::new();
+    for square in snipers {
+        let between = get_between(king_square, square);
+        let blockers = between & occupied;
+        if blockers.count() == 1 && !(blockers & friendly).is_empty() {
+            pins.push((blockers, between | square.bitboard()));
+        }
+    }
+    pins
 }
 
-pub fn get_white_pawn_double_pushes(pawns: &u64, empty: &u64) -> u64 {
-    let single = get_white_pawn_single_pushes(pawns, empty);
-    (single << 8) & empty & RANK_4
+// enpassant target is only usable if there is an enemy pawn that just made a double push
+fn get_enpassant_target(board: &BitBoard, color: &Color, enpassant: &Option<Square>) -> Bitboard {
+    let Some(square) = enpassant else {
+        return Bitboard::EMPTY;
+    };
+    let target = square.bitboard();
+    match color {
+        Color::White => match square.rank() == Rank::SIXTH && !(board.black_pawns & target.shift(Direction::South)).is_empty() {
+            true => target,
+            false => Bitboard::EMPTY,
+        },
+        Color::Black => match square.rank() == Rank::THIRD && !(board.white_pawns & target.shift(Direction::North)).is_empty() {
+            true => target,
+            false => Bitboard::EMPTY,
+        },
+    }
 }
 
-pub fn get_black_pawn_double_pushes(pawns: &u64, empty: &u64) -> u64 {
-    let single = get_black_pawn_single_pushes(pawns, empty);
-    (single >> 8) & empty & RANK_5
+pub fn get_knight_moves(knight: &Bitboard, targets: &Bitboard) -> Bitboard {
+    let steps = [
+        (Direction::North, Direction::NorthEast), (Direction::North, Direction::NorthWest),
+        (Direction::South, Direction::SouthEast), (Direction::South, Direction::SouthWest),
+        (Direction::East, Direction::NorthEast), (Direction::East, Direction::SouthEast),
+        (Direction::West, Direction::NorthWest), (Direction::West, Direction::SouthWest),
+    ];
+    let mut result = Bitboard::EMPTY;
+    for (first, second) in steps {
+        result |= knight.shift(first).shift(second);
+    }
+    result & *targets
 }
 
-pub fn get_white_pawn_east_attacks(pawns: &u64, targets: &u64) -> u64 {
-    (pawns << 9) & NOT_H_FILE & targets
+pub fn get_king_moves(king: &Bitboard, targets: &Bitboard) -> Bitboard {
+    let mut result = Bitboard::EMPTY;
+    for direction in Direction::ALL {
+        result |= king.shift(direction);
+    }
+    result & *targets
 }
 
-pub fn get_white_pawn_west_attacks(pawns: &u64, targets: &u64) -> u64 {
-    (pawns << 7) & NOT_A_FILE & targets
+pub fn get_white_pawn_single_pushes(pawns: &Bitboard, empty: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::North) & *empty
 }
 
-pub fn get_black_pawn_east_attacks(pawns: &u64, targets: &u64) -> u64 {
-    (pawns >> 7) & NOT_H_FILE & targets
+pub fn get_black_pawn_single_pushes(pawns: &Bitboard, empty: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::South) & *empty
 }
 
-pub fn get_black_pawn_west_attacks(pawns: &u64, targets: &u64) -> u64 {
-    (pawns >> 9) & NOT_A_FILE & targets
+pub fn get_white_pawn_double_pushes(pawns: &Bitboard, empty: &Bitboard) -> Bitboard {
+    let single = get_white_pawn_single_pushes(pawns, empty);
+    single.shift(Direction::North) & *empty & Rank::FOURTH.bitboard()
 }
 
-pub fn get_rook_moves(rook: &u64, occupied: &u64, friendly: &u64) -> u64 {
-    let mut result: u64 = 0;
-    let sq = rook.trailing_zeros() as usize;
-
-    let north_attack = ROOK_RAYS[NORTH][sq];
-    let north_blocker = north_attack & occupied;
-    if north_blocker != 0 {
-        let block_sq = north_blocker.trailing_zeros() as usize;
-        let attacks = north_attack ^ ROOK_RAYS[NORTH][block_sq];
-        result |= attacks;
-    } else {
-        result |= north_attack;
-    }
-
-    let east_attack = ROOK_RAYS[EAST][sq];
-    let east_blocker = east_attack & occupied;
-    if east_blocker != 0 {
-        let block_sq = east_blocker.trailing_zeros() as usize;
-        let attacks = east_attack ^ ROOK_RAYS[EAST][block_sq];
-        result |= attacks;
-    } else {
-        result |= east_attack;
-    }
-
-    let south_attack = ROOK_RAYS[SOUTH][sq];
-    let south_blocker = south_attack & occupied;
-    if south_blocker != 0 {
-        let block_sq = (63 - south_blocker.leading_zeros()) as usize;
-        let attacks = south_attack ^ ROOK_RAYS[SOUTH][block_sq];
-        result |= attacks;
-    } else {
-        result |= south_attack;
-    }
-
-    let west_attack = ROOK_RAYS[WEST][sq];
-    let west_blocker = west_attack & occupied;
-    if west_blocker != 0 {
-        let block_sq = (63 - west_blocker.leading_zeros()) as usize;
-        let attacks = west_attack ^ ROOK_RAYS[WEST][block_sq];
-        result |= attacks;
-    } else {
-        result |= west_attack;
-    }
-    result ^ (result & friendly)
+pub fn get_black_pawn_double_pushes(pawns: &Bitboard, empty: &Bitboard) -> Bitboard {
+    let single = get_black_pawn_single_pushes(pawns, empty);
+    single.shift(Direction::South) & *empty & Rank::FIFTH.bitboard()
 }
 
-pub fn get_bishop_moves(bishop: &u64, occupied: &u64, friendly: &u64) -> u64 {
-    let mut result: u64 = 0;
-    let sq = bishop.trailing_zeros() as usize;
+// east and west in the names of pawn attacks follow the bit order, so east attacks go towards the a file
+pub fn get_white_pawn_east_attacks(pawns: &Bitboard, targets: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::NorthWest) & *targets
+}
 
-    let ne_attack = BISHOP_RAYS[NORTHEAST-4][sq];
-    let ne_blocker = ne_attack & occupied;
-    if ne_blocker != 0 {
-        let block_sq = ne_blocker.trailing_zeros() as usize;
-        let attacks = ne_attack ^ BISHOP_RAYS[NORTHEAST-4][block_sq];
-        result |= attacks;
-    } else {
-        result |= ne_attack;
-    }
-    
-    let nw_attack = BISHOP_RAYS[NORTHWEST-4][sq];
-    let ne_blocker = nw_attack & occupied;
-    if ne_blocker != 0 {
-        let block_sq = ne_blocker.trailing_zeros() as usize;
-        let attacks = nw_attack ^ BISHOP_RAYS[NORTHWEST-4][block_sq];
-        result |= attacks;
-    } else {
-        result |= nw_attack;
-    }
+pub fn get_white_pawn_west_attacks(pawns: &Bitboard, targets: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::NorthEast) & *targets
+}
 
-    let se_attack = BISHOP_RAYS[SOUTHEAST-4][sq];
-    let se_blocker = se_attack & occupied;
-    if se_blocker != 0 {
-        let block_sq = (63 - se_blocker.leading_zeros()) as usize;
-        let attacks = se_attack ^ BISHOP_RAYS[SOUTHEAST-4][block_sq];
-        result |= attacks;
-    } else {
-        result |= se_attack;
-    }
+pub fn get_black_pawn_east_attacks(pawns: &Bitboard, targets: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::SouthWest) & *targets
+}
 
-    let sw_attack = BISHOP_RAYS[SOUTHWEST-4][sq];
-    let sw_blocker = sw_attack & occupied;
-    if sw_blocker != 0 {
-        let block_sq = (63 - sw_blocker.leading_zeros()) as usize;
-        let attacks = sw_attack ^ BISHOP_RAYS[SOUTHWEST-4][block_sq];
-        result |= attacks;
-    } else {
-        result |= sw_attack;
-    }
-    result ^ (result & friendly)
+pub fn get_black_pawn_west_attacks(pawns: &Bitboard, targets: &Bitboard) -> Bitboard {
+    pawns.shift(Direction::SouthEast) & *targets
 }
 
-pub fn is_game_won(_board: &BitBoard, _color: &Color) -> bool {
-    false
+pub fn get_rook_moves(rook: Square, occupied: &Bitboard, friendly: &Bitboard) -> Bitboard {
+    get_rook_attacks(rook, *occupied) & !*friendly
 }
 
-pub fn is_game_drawn(_board: &BitBoard, _color: &Color) -> bool {
-    false
+pub fn get_bishop_moves(bishop: Square, occupied: &Bitboard, friendly: &Bitboard) -> Bitboard {
+    get_bishop_attacks(bishop, *occupied) & !*friendly
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GameState {
     Normal, Check, Checkmate, Stalemate,
+    FiftyMoves, SeventyFiveMoves, ThreefoldRepetition, FivefoldRepetition,
+    InsufficientMaterial, DeadPosition,
+}
+
+// position is the state after the move, history contains earlier positions as FEN strings
+pub fn game_state(position: &Position, history: &[String]) -> Result<GameState, FenError> {
+    Ok(game_state_from_hashes(position, &repetition_hashes(history)?))
 }
 
-pub fn game_state(board: &mut BitBoard, color: &Color) -> GameState {
-    let opp_color = color.opposite(); 
+pub fn repetition_hashes(history: &[String]) -> Result<Vec<u64>, FenError> {
+    history
+        .iter()
+        .map(|fen| Position::from_fen(fen).map(|previous| previous.repetition_hash()))
+        .collect()
+}
+
+// same as game_state, but history contains the repetition hashes of earlier positions
+pub fn game_state_from_hashes(position: &Position, history: &[u64]) -> GameState {
+    let color = position.color.opposite();
+    let opp_color = position.color.clone();
+    let board = &position.board;
 
-    let captures = get_capture_map(&board, color);
+    let captures = get_capture_map(board, &color);
     let king = board.get_bitboard_by_piece(&Piece::King, &opp_color);
-    let check = king & captures != 0;
-
-    let moves = get_possible_moves(board, &opp_color);
-    let mut no_moves = true;
-    for mov in moves {
-        board.apply_move(&mov, &opp_color);
-        let king = board.get_bitboard_by_piece(&Piece::King, &opp_color);
-        let captures = get_capture_map(board, color);
-        let no_check = king & captures == 0;
-        board.apply_move(&mov, &opp_color);
-        if no_check {
-            no_moves = false;
-            break;
-        }
-    }
+    let check = !(king & captures).is_empty();
+
+    let no_moves = get_legal_moves(board, &opp_color, &position.enpassant, &position.castling).is_empty();
 
     match (check, no_moves) {
         (true, true) => GameState::Checkmate,
-        (true, false) => GameState::Check,
         (false, true) => GameState::Stalemate,
-        (false, false) => GameState::Normal,
+        (true, false) => get_draw(position, history).unwrap_or(GameState::Check),
+        (false, false) => get_draw(position, history).unwrap_or(GameState::Normal),
     }
 }
 
-pub fn move_to_string(board: &mut BitBoard, mov: &Move, color: &Color, check: bool, checkmate:bool) -> String {
-    // TODO: castling
-    let have_capture = mov.capture.is_some();
+// checkmate takes precedence, so it should be checked before calling this;
+// history contains the repetition hashes of earlier positions
+fn get_draw(position: &Position, history: &[u64]) -> Option<GameState> {
+    if is_insufficient_material(&position.board) {
+        return Some(GameState::InsufficientMaterial);
+    }
+    if is_dead_position(&position.board, &position.enpassant) {
+        return Some(GameState::DeadPosition);
+    }
 
-    let mut candidates = get_moves_from(board, &mov.piece, have_capture, mov.to, color);
+    let hash = position.repetition_hash();
+    let repetitions = 1 + history.iter().filter(|previous| **previous == hash).count();
 
-    let opp_color = color.opposite();
+    if repetitions >= 5 {
+        return Some(GameState::FivefoldRepetition);
+    }
+    if position.halfmoves >= 150 {
+        return Some(GameState::SeventyFiveMoves);
+    }
+    if repetitions >= 3 {
+        return Some(GameState::ThreefoldRepetition);
+    }
+    if position.halfmoves >= 100 {
+        return Some(GameState::FiftyMoves);
+    }
+    None
+}
 
-    let mut cand = Vec::new();
-    while candidates != 0 {
-        let from = candidates.trailing_zeros() as u8;
-        let piece_from = 1 << from as u8;
-        candidates = candidates & !piece_from;
-        let mov = Move { from, to: mov.to, promotion: mov.promotion, capture: mov.capture, castling: false, piece: mov.piece }; // TODO
-        board.apply_move(&mov, color);
-        let captures = get_capture_map(&board, &opp_color);
-        let king = board.get_king_by_color(color);
-        board.apply_move(&mov, color);
-        if from == mov.from {
-            continue;
+// K vs K, K+minor vs K, or only bishops on squares of the same color
+fn is_insufficient_material(board: &BitBoard) -> bool {
+    let pawns = board.white_pawns | board.black_pawns;
+    let majors = board.white_rooks | board.black_rooks | board.white_queens | board.black_queens;
+    if !(pawns | majors).is_empty() {
+        return false;
+    }
+    let knights = board.white_knights | board.black_knights;
+    let bishops = board.white_bishops | board.black_bishops;
+    if (knights | bishops).count() <= 1 {
+        return true;
+    }
+    knights.is_empty() && ((bishops & DARK_SQUARES).is_empty() || (bishops & !DARK_SQUARES).is_empty())
+}
+
+// conservative check for blocked pawn chains: only kings and pawns are left,
+// every pawn is blocked by an enemy pawn, no pawn can capture
+// and no king can reach an undefended enemy pawn
+fn is_dead_position(board: &BitBoard, enpassant: &Option<Square>) -> bool {
+    let white_pieces = board.white_knights | board.white_bishops | board.white_rooks | board.white_queens;
+    let black_pieces = board.black_knights | board.black_bishops | board.black_rooks | board.black_queens;
+    if !(white_pieces | black_pieces).is_empty() || enpassant.is_some() {
+        return false;
+    }
+    let white_pawns = board.white_pawns;
+    let black_pawns = board.black_pawns;
+    if white_pawns.is_empty() || white_pawns.shift(Direction::North) != black_pawns {
+        return false;
+    }
+
+    let white_attacks = get_white_pawn_east_attacks(&white_pawns, &Bitboard::FULL) | get_white_pawn_west_attacks(&white_pawns, &Bitboard::FULL);
+    let black_attacks = get_black_pawn_east_attacks(&black_pawns, &Bitboard::FULL) | get_black_pawn_west_attacks(&black_pawns, &Bitboard::FULL);
+    if !(white_attacks & black_pawns).is_empty() {
+        return false;
+    }
+
+    let white_region = get_king_region(&board.white_king, &!(white_pawns | black_attacks));
+    let black_region = get_king_region(&board.black_king, &!(black_pawns | white_attacks));
+    (white_region & black_pawns).is_empty() && (black_region & white_pawns).is_empty()
+}
+
+// squares the king can reach by walking through allowed squares
+fn get_king_region(king: &Bitboard, allowed: &Bitboard) -> Bitboard {
+    let mut region = *king;
+    loop {
+        let next = region | get_king_moves(&region, allowed);
+        if next == region {
+            return region;
         }
-        if king & captures == 0 {
-            cand.push(from)
-        };
+        region = next;
     }
-    let need_file = cand.iter().find(|&&m| m % 8 == mov.from % 8).is_some();
-    let need_rank = cand.iter().find(|&&m| m / 8 == mov.from / 8).is_some();
+}
 
-    let mut move_str = String::new();
+// check, checkmate and disambiguation are computed on a copy of the board before the move
+pub fn move_to_string(board: &BitBoard, mov: &Move, color: &Color) -> String {
+    let mut board = *board;
+    let mut move_str = match mov.castling {
+        true => castling_to_string(mov),
+        false => piece_move_to_string(&board, mov, color),
+    };
 
-    if mov.piece != Piece::Pawn {
-        move_str.push(piece_to_letter(mov.piece));
+    let opp_color = color.opposite();
+    board.apply_move(mov, color);
+    let check = !(board.get_king_by_color(&opp_color) & get_capture_map(&board, color)).is_empty();
+    if check {
+        // castling is never legal in check, but an enpassant capture can be the only escape
+        let has_reply = !get_legal_moves(&board, &opp_color, &mov.enpassant_square(), &CastlingAvailability::default()).is_empty();
+        move_str.push(match has_reply {
+            true => '+',
+            false => '#',
+        });
     }
+    move_str
+}
 
-    if need_file {
-        move_str.push(num_to_file(mov.from));
+fn castling_to_string(mov: &Move) -> String {
+    match mov.to.file() < mov.from.file() {
+        true => String::from("O-O-O"),
+        false => String::from("O-O"),
     }
-    if need_rank {
-        move_str.push(num_to_rank(mov.from));
+}
+
+fn piece_move_to_string(board: &BitBoard, mov: &Move, color: &Color) -> String {
+    let mut move_str = String::new();
+
+    match mov.piece {
+        Piece::Pawn => if mov.capture.is_some() {
+            move_str.push(mov.from.file().to_char());
+        },
+        piece => {
+            move_str.push(piece_to_letter(piece));
+            // other pieces of the same type that can legally reach the same square
+            let others: Vec<Square> = get_legal_moves(board, color, &None, &CastlingAvailability::default())
+                .into_iter()
+                .filter(|other| other.piece == piece && other.to == mov.to && other.from != mov.from)
+                .map(|other| other.from)
+                .collect();
+            let same_file = others.iter().any(|from| from.file() == mov.from.file());
+            let same_rank = others.iter().any(|from| from.rank() == mov.from.rank());
+            // file is preferred, then rank, and the full square only if neither is enough
+            let (need_file, need_rank) = match (others.is_empty(), same_file, same_rank) {
+                (true, _, _) => (false, false),
+                (false, false, _) => (true, false),
+                (false, true, false) => (false, true),
+                (false, true, true) => (true, true),
+            };
+            if need_file {
+                move_str.push(mov.from.file().to_char());
+            }
+            if need_rank {
+                move_str.push(mov.from.rank().to_char());
+            }
+        },
     }
 
-    if have_capture {
+    if mov.capture.is_some() {
         move_str.push('x');
     }
 
-    move_str.push(num_to_file(mov.to));
-    move_str.push(num_to_rank(mov.to));
+    move_str.push_str(&mov.to.to_string());
 
     if let Some(promotion) = mov.promotion {
         move_str.push('=');
         move_str.push(piece_to_letter(promotion));
     }
-   
-    if check & !checkmate {
-        move_str.push('+');
-    }
-    if checkmate {
-        move_str.push('#');
-    }
-    // TODO: add enpassant ( e.p.)
-
     move_str
 }
 
-fn piece_to_letter(piece: Piece) -> char {
+// move doesn't leave own king in check
+fn is_legal(board: &mut BitBoard, mov: &Move, color: &Color) -> bool {
+    board.apply_move(mov, color);
+    let captures = get_capture_map(board, &color.opposite());
+    let no_check = (board.get_king_by_color(color) & captures).is_empty();
+    board.undo_move(mov, color);
+    no_check
+}
+
+pub fn piece_to_letter(piece: Piece) -> char {
     match piece {
         Piece::Pawn => 'P',
         Piece::Knight => 'N',
@@ -555,214 +547,204 @@ fn piece_to_letter(piece: Piece) -> char {
     }
 }
 
-fn num_to_rank(pos: u8) -> char {
-    (b'1' + (pos / 8)) as char
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveParseError {
+    InvalidSyntax,
+    UnknownPiece(char),
+    NoSuchPiece,
+    Ambiguous,
+    WrongCaptureFlag,
+    Illegal,
 }
 
-fn num_to_file(pos: u8) -> char {
-    (b'a' + 7 - (pos % 8)) as char
+impl std::fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveParseError::InvalidSyntax => write!(f, "Cannot parse move"),
+            MoveParseError::UnknownPiece(letter) => write!(f, "Unknown piece '{}'", letter),
+            MoveParseError::NoSuchPiece => write!(f, "No such piece!"),
+            MoveParseError::Ambiguous => write!(f, "Ambiguous starting position!"),
+            MoveParseError::WrongCaptureFlag => write!(f, "Capture flag corrupted!"),
+            MoveParseError::Illegal => write!(f, "Illegal move!"),
+        }
+    }
 }
 
-pub fn string_to_move(board: &mut BitBoard, mov: String, color: &Color) -> Result<Move, String> {
-    let pattern = r"([KQRBN]?)([a-h])?([1-8])?(x)?([a-h][1-8])(=[QRBN])?( e\.p\.)?";
-
-    let re = Regex::new(pattern).unwrap();
-
-    match re.captures(mov.as_str()) {
-        Some(caps) => {
-            let piece = caps.get(1).map_or(Piece::Pawn, |m| letter_to_piece(m.as_str()));
-            let from_file = caps.get(2).map_or(None, |m| Some(m.as_str()));
-            let from_rank = caps.get(3).map_or(None, |m| Some(m.as_str()));
-            let have_capture = caps.get(4).map_or(false, |_m| true);
-            let to = caps.get(5).map_or(0, |m| field_to_num(m.as_str()));
-            let promotion = caps.get(6).map_or(None, |m| Some(letter_to_piece(&m.as_str()[1..])));
-            let enpassant = caps.get(7).map_or(false, |_m| true);
-
-            if let Some(promote_to) = promotion {
-                if piece != Piece::Pawn {
-                        return Err("Cannot promote piece".into());
-                }
-                if promote_to == Piece::Pawn || promote_to == Piece::King {
-                        return Err("Cannot promote to this piece".into());
-                }
-                let correct_rank = match color {
-                    Color::White => 1 << to as u8 & WHITE_PROMOTION != 0,
-                    Color::Black => 1 << to as u8  & BLACK_PROMOTION != 0,
-                };
-                if !correct_rank {
-                        return Err("Pawn not on a promotion rank".into());
-                }
-            }
+impl std::error::Error for MoveParseError {}
+
+// move as written, before it is matched against the legal moves
+struct MoveSpec {
+    // none for coordinate moves, which don't name the piece
+    piece: Option<Piece>,
+    from_file: Option<File>,
+    from_rank: Option<Rank>,
+    // none for castling
+    to: Option<Square>,
+    promotion: Option<Piece>,
+    // none when notation doesn't say if the move is a capture
+    capture: Option<bool>,
+    enpassant: bool,
+    // some(queenside) for castling
+    castling: Option<bool>,
+}
 
-            let mut candidates = match (from_file, from_rank) {
-                (Some(file), Some(rank)) => {
-                    let cand = 1 << field_to_num(format!("{}{}", file, rank).as_str());
-                    let orig = board.check_capture(&cand, &color.opposite());
-                    if orig != Some(piece) {
-                        return Err("Wrong start square".into());
-                    }
-                    cand
-                },
-                (Some(file), None) => {
-                    let file = file_to_num(file.chars().next().unwrap());
-                    let move_map = get_moves_from(board, &piece, have_capture, to, color);
-                    let file_map = ROOK_RAYS[NORTH][0+file as usize] | (1 << (0+file));
-                    move_map & file_map
-                },
-                (None, Some(rank)) => {
-                    let rank = rank_to_num(rank.chars().next().unwrap());
-                    let move_map = get_moves_from(board, &piece, have_capture, to, color);
-                    let rank_map = ROOK_RAYS[EAST][(rank as usize)*8 + 0] | (1 << (0+rank*8));
-                    move_map & rank_map
-                },
-                (None, None) => {
-                    get_moves_from(board, &piece, have_capture, to, color)
-                },
-            };
+impl MoveSpec {
+    fn castling(queenside: bool, enpassant: bool) -> MoveSpec {
+        MoveSpec { piece: Some(Piece::King), from_file: None, from_rank: None, to: None, promotion: None, capture: Some(false), enpassant, castling: Some(queenside) }
+    }
 
-            let mut current = candidates;
-            let capture = board.check_capture(&(1<<to), color);
-            if (capture.is_none() && have_capture) || (capture.is_some() && !have_capture) {
-                return Err("Capture flag corrupted!".into())
-            };
+    fn origin_matches(&self, square: Square) -> bool {
+        self.from_file.is_none_or(|file| square.file() == file) && self.from_rank.is_none_or(|rank| square.rank() == rank)
+    }
 
-            let opp_color = color.opposite();
+    fn matches(&self, mov: &Move) -> bool {
+        match (self.castling, mov.castling) {
+            (Some(queenside), true) => (mov.to.file() < mov.from.file()) == queenside,
+            (Some(_), false) => false,
+            // castling written as a king move is accepted only in coordinate notation
+            (None, true) => self.piece.is_none() && self.to == Some(mov.to) && self.origin_matches(mov.from),
+            (None, false) => self.piece.is_none_or(|piece| piece == mov.piece)
+                && self.to == Some(mov.to)
+                && self.promotion == mov.promotion
+                && self.origin_matches(mov.from),
+        }
+    }
 
-            let capture_my = board.check_capture(&(1<<to), &opp_color);
-            if capture_my.is_some() {
-                return Err("Cannot move to field occupied by your own piece!".into())
-            }
+    fn has_piece(&self, board: &BitBoard, color: &Color) -> bool {
+        let pieces = match (self.piece, color) {
+            (Some(piece), _) => board.get_bitboard_by_piece(&piece, color),
+            (None, Color::White) => board.get_white(),
+            (None, Color::Black) => board.get_black(),
+        };
+        pieces.into_iter().any(|square| self.origin_matches(square))
+    }
+}
 
-            while current != 0 {
-                let from = current.trailing_zeros() as u8;
-                let piece_from = 1 << from as u8;
-                current = current & !piece_from;
-                let mov = Move { from, to, promotion, capture, castling: false, piece }; // TODO
-                board.apply_move(&mov, color);
-                let captures = get_capture_map(&board, &opp_color);
-                let king = board.get_king_by_color(color);
-                board.apply_move(&mov, color);
-                if king & captures != 0 {
-                    candidates ^= piece_from;
-                };
-            }
+// accepts SAN (Nbd7, exd6 e.p., e8=Q+, O-O, 0-0-0), long algebraic (Ng1-f3, e7xd8=Q) and coordinate moves (e2e4, e7e8q)
+fn parse_move(mov: &str) -> Result<MoveSpec, MoveParseError> {
+    let mov = mov.trim();
+    let (mov, enpassant) = match mov.strip_suffix("e.p.") {
+        Some(rest) => (rest.trim_end(), true),
+        None => (mov, false),
+    };
+    let mov = mov.trim_end_matches(['+', '#', '!', '?']);
+    match mov {
+        "O-O" | "0-0" => return Ok(MoveSpec::castling(false, enpassant)),
+        "O-O-O" | "0-0-0" => return Ok(MoveSpec::castling(true, enpassant)),
+        _ => {},
+    }
+
+    let mut bytes = mov.as_bytes();
+    let piece = match bytes.first() {
+        Some(letter) if letter.is_ascii_uppercase() => {
+            bytes = &bytes[1..];
+            Some(letter_to_piece(*letter).ok_or(MoveParseError::UnknownPiece(*letter as char))?)
+        },
+        _ => None,
+    };
 
-            if candidates.count_ones() > 1 {
-                return Err("Ambiguous starting position!".into())
-            }
-            if candidates == 0 {
-                return Err("No such piece!".into())
-            }
-            let from = candidates.trailing_zeros() as u8;
+    let promotion = match bytes {
+        [rest @ .., b'=', letter] => {
+            bytes = rest;
+            Some(letter_to_piece(letter.to_ascii_uppercase()).ok_or(MoveParseError::UnknownPiece(*letter as char))?)
+        },
+        [rest @ .., b'1'..=b'8', letter @ (b'q' | b'r' | b'b' | b'n' | b'Q' | b'R' | b'B' | b'N')] => {
+            bytes = &bytes[..rest.len() + 1];
+            letter_to_piece(letter.to_ascii_uppercase())
+        },
+        _ => None,
+    };
 
-            println!("({}) {:?}, from {} to {}, capture: {:?}, promotion: {:?}, enpassant: {}", mov, piece, from, to, capture, promotion, enpassant);
-            Ok(Move {
-                from, to, promotion, capture, castling: false, piece,
-            })
+    let to = match bytes {
+        [rest @ .., file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+            bytes = rest;
+            Square::new(File::ALL[(file - b'a') as usize], Rank::ALL[(rank - b'1') as usize])
         },
-        None => {
-            println!("No match found.");
-            Err("Cannot parse move".into())
+        _ => return Err(MoveParseError::InvalidSyntax),
+    };
+
+    let separator = match bytes {
+        [rest @ .., separator @ (b'x' | b'-')] => {
+            bytes = rest;
+            Some(*separator)
         },
+        _ => None,
+    };
+
+    let (from_file, from_rank) = match bytes {
+        [] => (None, None),
+        [file @ b'a'..=b'h'] => (Some(File::ALL[(file - b'a') as usize]), None),
+        [rank @ b'1'..=b'8'] => (None, Some(Rank::ALL[(rank - b'1') as usize])),
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (Some(File::ALL[(file - b'a') as usize]), Some(Rank::ALL[(rank - b'1') as usize])),
+        _ => return Err(MoveParseError::InvalidSyntax),
+    };
+
+    // pawn captures always name the file the pawn comes from
+    if piece.is_none() && separator.is_some() && from_file.is_none() {
+        return Err(MoveParseError::InvalidSyntax);
     }
+
+    let coordinates = piece.is_none() && from_file.is_some() && from_rank.is_some();
+    let capture = match (separator, coordinates) {
+        (Some(b'x'), _) => Some(true),
+        (Some(_), _) => Some(false),
+        (None, true) => None,
+        (None, false) => Some(false),
+    };
+    let piece = match (piece, coordinates) {
+        (None, false) => Some(Piece::Pawn),
+        (piece, _) => piece,
+    };
+
+    Ok(MoveSpec { piece, from_file, from_rank, to: Some(to), promotion, capture, enpassant, castling: None })
 }
 
-fn letter_to_piece(letter: &str) -> Piece {
-    match letter {
-        "K" => Piece::King,
-        "Q" => Piece::Queen,
-        "R" => Piece::Rook,
-        "B" => Piece::Bishop,
-        "N" => Piece::Knight,
-        _ => Piece::Pawn,
+pub fn string_to_move(board: &BitBoard, mov: &str, color: &Color, enpassant: &Option<Square>, castling: &CastlingAvailability) -> Result<Move, MoveParseError> {
+    let spec = parse_move(mov)?;
+
+    let mut found: Option<Move> = None;
+    for mov in get_legal_moves(board, color, enpassant, castling).into_iter().filter(|mov| spec.matches(mov)) {
+        if found.is_some() {
+            return Err(MoveParseError::Ambiguous);
+        }
+        found = Some(mov);
     }
-}
 
-fn rank_to_num(rank: char) -> u8 {
-    rank as u8 - b'1'
+    let Some(mov) = found else {
+        return match spec.has_piece(board, color) {
+            true => Err(MoveParseError::Illegal),
+            false => Err(MoveParseError::NoSuchPiece),
+        };
+    };
+    let wrong_capture = spec.capture.is_some_and(|capture| capture != mov.capture.is_some());
+    if wrong_capture || (spec.enpassant && !mov.enpassant) {
+        return Err(MoveParseError::WrongCaptureFlag);
+    }
+    Ok(mov)
 }
 
-fn file_to_num(file: char) -> u8 {
-    7 - (file as u8 - b'a')
+// long algebraic notation used by UCI, e.g. e2e4, e7e8q or e1g1 for castling
+pub fn move_to_lan(mov: &Move) -> String {
+    let mut result = format!("{}{}", mov.from, mov.to);
+    if let Some(promotion) = mov.promotion {
+        result.push(piece_to_letter(promotion).to_ascii_lowercase());
+    }
+    result
 }
 
-pub fn field_to_num(field: &str) -> u8 {
-    let mut c = field.chars();
-    let file = c.next().unwrap();
-    let rank = c.next().unwrap();
-    let file = file_to_num(file);
-    let rank = rank_to_num(rank);
-    rank*8 + file
-}
 
-fn get_moves_from(board: &BitBoard, piece: &Piece, capture: bool, to: u8, color: &Color) -> u64 {
-    let (pawns, knights, bishops, rooks, queens, king, enemy) = match color {
-        Color::Black => (board.black_pawns, board.black_knights, board.black_bishops, board.black_rooks, board.black_queens, board.black_king, 
-                       board.get_white()),
-        Color::White => (board.white_pawns, board.white_knights, board.white_bishops, board.white_rooks, board.white_queens, board.white_king,
-                       board.get_black()),
-    };
-    let my = pawns | knights | bishops | rooks | queens | king;
-    match (piece, capture) {
-        (Piece::Pawn, true) => {
-            match color {
-                Color::White => {
-                    let west = get_white_pawn_west_attacks(&pawns, &enemy);
-                    let west = (west & (1<<to)) >> 7;
-                    let east = get_white_pawn_east_attacks(&pawns, &enemy);
-                    let east = (east & (1<<to)) >> 9;
-                    east | west
-                },
-                Color::Black => {
-                    let west = get_black_pawn_west_attacks(&pawns, &enemy);
-                    let west = (west & (1<<to)) << 9;
-                    let east = get_black_pawn_east_attacks(&pawns, &enemy);
-                    let east = (east & (1<<to)) << 7;
-                    east | west
-                },
-            }
-        },
-        (Piece::Pawn, false) => {
-            match color {
-                Color::White => {
-                    let empty = !(my|enemy);
-                    let single = get_white_pawn_single_pushes(&pawns, &empty);
-                    let single = (single & (1<<to)) >> 8;
-                    let double = get_white_pawn_double_pushes(&pawns, &empty);
-                    let double = (double & (1<<to)) >> 16;
-                    single | double
-                },
-                Color::Black => {
-                    let empty = !(my|enemy);
-                    let single = get_black_pawn_single_pushes(&pawns, &empty);
-                    let single = (single & (1<<to)) << 8;
-                    let double = get_black_pawn_double_pushes(&pawns, &empty);
-                    let double = (double & (1<<to)) << 16;
-                    single | double
-                },
-            }
-        }, 
-        (Piece::Knight, _) => {
-            get_knight_moves(&(1<<to), &knights)
-        },
-        (Piece::Bishop, _) => {
-            get_bishop_moves(&(1<<to), &(enemy|my), &enemy) & bishops
-        },
-        (Piece::Rook, _) => {
-            get_rook_moves(&(1<<to), &(enemy|my), &enemy) & rooks
-        },
-        (Piece::Queen, _) => {
-            let bishop = get_bishop_moves(&(1<<to), &(enemy|my), &enemy);
-            let rook = get_rook_moves(&(1<<to), &(enemy|my), &enemy);
-            (bishop | rook) & queens
-        },
-        (Piece::King, _) => {
-            get_king_moves(&(1<<to), &king)
-        },
+fn letter_to_piece(letter: u8) -> Option<Piece> {
+    match letter {
+        b'K' => Some(Piece::King),
+        b'Q' => Some(Piece::Queen),
+        b'R' => Some(Piece::Rook),
+        b'B' => Some(Piece::Bishop),
+        b'N' => Some(Piece::Knight),
+        _ => None,
     }
 }
 
-pub fn get_capture_map(board: &BitBoard, color: &Color) -> u64 {
+pub fn get_capture_map(board: &BitBoard, color: &Color) -> Bitboard {
     let (pawns, knights, bishops, rooks, queens, king, enemy) = match color {
         Color::Black => (board.black_pawns, board.black_knights, board.black_bishops, board.black_rooks, board.black_queens, board.black_king, board.get_white()),
         Color::White => (board.white_pawns, board.white_knights, board.white_bishops, board.white_rooks, board.white_queens, board.white_king, board.get_black()),
@@ -771,35 +753,27 @@ pub fn get_capture_map(board: &BitBoard, color: &Color) -> u64 {
     let all_pieces = friendly | enemy;
     let empty = !all_pieces;
 
-    let mut result = 0;
+    let mut result = Bitboard::EMPTY;
 
     result |= get_knight_moves(&knights, &(empty | enemy));
     result |= get_king_moves(&king, &(empty | enemy));
 
+    // enpassant captures are not needed here, as they can never capture the king
+    // empty squares are included, as the king cannot pass through them while castling
     if color == &Color::White {
-        // TODO: enpassant
-        result |= get_white_pawn_east_attacks(&pawns, &enemy);
-        result |= get_white_pawn_west_attacks(&pawns, &enemy);
+        result |= get_white_pawn_east_attacks(&pawns, &(empty | enemy));
+        result |= get_white_pawn_west_attacks(&pawns, &(empty | enemy));
     } else {
-        // TODO: enpassant
-        result |= get_black_pawn_east_attacks(&pawns, &enemy);
-        result |= get_black_pawn_west_attacks(&pawns, &enemy);
+        result |= get_black_pawn_east_attacks(&pawns, &(empty | enemy));
+        result |= get_black_pawn_west_attacks(&pawns, &(empty | enemy));
     }
 
-    let mut current = rooks | queens;
-    while current != 0 {
-        let from = current.trailing_zeros() as u8;
-        let rook = 1 << from;
-        current = current ^ rook;
-        result |= get_rook_moves(&rook, &all_pieces, &friendly);
+    for from in rooks | queens {
+        result |= get_rook_moves(from, &all_pieces, &friendly);
     }
 
-    current = bishops | queens;
-    while current != 0 {
-        let from = current.trailing_zeros() as u8;
-        let bishop = 1 << from;
-        current = current ^ bishop;
-        result |= get_bishop_moves(&bishop, &all_pieces, &friendly);
+    for from in bishops | queens {
+        result |= get_bishop_moves(from, &all_pieces, &friendly);
     }
 
     result
@@ -807,12 +781,16 @@ pub fn get_capture_map(board: &BitBoard, color: &Color) -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use crate::{engine::generate_board_from_fen, print_bitboard, print_board};
+    use crate::{engine::{generate_bit_board, generate_board_from_fen, perft::{perft, perft_divide}}, print_bitboard, print_board};
 
     use super::*;
 
-    fn get_for_knight(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    fn get_for_knight(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = !board.get_black() | board.get_white();
         let knights = match color {
             Color::White => board.white_knights,
@@ -820,7 +798,7 @@ mod tests {
         };
         let moves = get_knight_moves(&knights, &targets);
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -835,8 +813,8 @@ mod tests {
         assert_eq!(get_for_knight("8/6N1/8/8/8/8/8/8", Color::White), 0x0800080500000000);
     }
 
-    fn get_for_king(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_for_king(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = !board.get_black() | board.get_white();
         let king = match color {
             Color::White => board.white_king,
@@ -844,7 +822,7 @@ mod tests {
         };
         let moves = get_king_moves(&king, &targets);
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -859,15 +837,15 @@ mod tests {
         assert_eq!(get_for_king("8/7K/8/8/8/8/8/8", Color::White), 0x0302030000000000);
     }
 
-    fn get_single_pushes(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_single_pushes(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = !board.get_black() & !board.get_white();
         let moves = match color {
             Color::White => get_white_pawn_single_pushes(&board.white_pawns, &targets),
             Color::Black => get_black_pawn_single_pushes(&board.black_pawns, &targets),
         };
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -881,15 +859,15 @@ mod tests {
         assert_eq!(get_single_pushes("8/8/8/8/8/k7/P7/8", Color::White), 0x0000000000000000);
     }
 
-    fn get_double_pushes(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_double_pushes(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = !board.get_black() & !board.get_white();
         let moves = match color {
             Color::White => get_white_pawn_double_pushes(&board.white_pawns, &targets),
             Color::Black => get_black_pawn_double_pushes(&board.black_pawns, &targets),
         };
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -905,8 +883,8 @@ mod tests {
         assert_eq!(get_double_pushes("8/8/8/8/k7/8/P7/8", Color::White), 0x0000000000000000);
     }
 
-    fn get_east_captures(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_east_captures(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = match color {
             Color::White => board.get_black(),
             Color::Black => board.get_white(),
@@ -916,7 +894,7 @@ mod tests {
             Color::Black => get_black_pawn_east_attacks(&board.black_pawns, &targets),
         };
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -935,18 +913,18 @@ mod tests {
         assert_eq!(get_east_captures("8/8/8/8/8/3P4/2n5/8", Color::White), 0x0000000000000000);
 
         assert_eq!(get_east_captures("8/3p4/4N3/8/8/8/8/8", Color::Black), 0x0000000000000000);
-        assert_eq!(get_east_captures("8/2p4/2N5/8/8/8/8/8", Color::Black), 0x0000400000000000);
+        assert_eq!(get_east_captures("8/2p5/1N6/8/8/8/8/8", Color::Black), 0x0000400000000000);
         assert_eq!(get_east_captures("8/p7/1N6/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_east_captures("8/p7/7N/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_east_captures("8/3p4/4n3/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_east_captures("8/3p4/2n5/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_east_captures("8/p7/8/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_east_captures("8/4N3/3p4/8/8/8/8/8", Color::Black), 0x0000000000000000);
-        assert_eq!(get_east_captures("8/2N5/3p4/8/8/3P8/8/8", Color::Black), 0x0000000000000000);
+        assert_eq!(get_east_captures("8/2N5/3p4/8/8/3P4/8/8", Color::Black), 0x0000000000000000);
     }
 
-    fn get_west_captures(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_west_captures(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let targets = match color {
             Color::White => board.get_black(),
             Color::Black => board.get_white(),
@@ -956,7 +934,7 @@ mod tests {
             Color::Black => get_black_pawn_west_attacks(&board.black_pawns, &targets),
         };
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -975,18 +953,18 @@ mod tests {
         assert_eq!(get_west_captures("8/8/8/8/8/3P4/2n5/8", Color::White), 0x0000000000000000);
 
         assert_eq!(get_west_captures("8/3p4/4N3/8/8/8/8/8", Color::Black), 0x0000080000000000);
-        assert_eq!(get_west_captures("8/2p4/2N5/8/8/8/8/8", Color::Black), 0x0000000000000000);
+        assert_eq!(get_west_captures("8/2p5/1N6/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_west_captures("8/p7/1N6/8/8/8/8/8", Color::Black), 0x0000400000000000);
         assert_eq!(get_west_captures("8/p7/7N/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_west_captures("8/3p4/4n3/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_west_captures("8/3p4/2n5/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_west_captures("8/p7/8/8/8/8/8/8", Color::Black), 0x0000000000000000);
         assert_eq!(get_west_captures("8/4N3/3p4/8/8/8/8/8", Color::Black), 0x0000000000000000);
-        assert_eq!(get_west_captures("8/2N5/3p4/8/8/3P8/8/8", Color::Black), 0x0000000000000000);
+        assert_eq!(get_west_captures("8/2N5/3p4/8/8/3P4/8/8", Color::Black), 0x0000000000000000);
     }
 
-    fn get_for_rook(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_for_rook(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let occupied = board.get_white() | board.get_black();
         let friendly = match color {
             Color::White => board.get_white(),
@@ -996,9 +974,9 @@ mod tests {
             Color::White => board.white_rooks,
             Color::Black => board.black_rooks,
         };
-        let moves = get_rook_moves(&rooks, &occupied, &friendly);
+        let moves = get_rook_moves(rooks.first().unwrap(), &occupied, &friendly);
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -1025,8 +1003,8 @@ mod tests {
         assert_eq!(get_for_rook("8/8/8/3r4/8/3n4/8/8", Color::Black), 0x101010ef10000000);
     }
 
-    fn get_for_bishop(fen: &str, color: Color) -> u64 {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
+    fn get_for_bishop(fen: &str, color: Color) -> Bitboard {
+        let board = generate_board_from_fen(fen).unwrap();
         let occupied = board.get_white() | board.get_black();
         let friendly = match color {
             Color::White => board.get_white(),
@@ -1036,9 +1014,9 @@ mod tests {
             Color::White => board.white_bishops,
             Color::Black => board.black_bishops,
         };
-        let moves = get_bishop_moves(&bishops, &occupied, &friendly);
+        let moves = get_bishop_moves(bishops.first().unwrap(), &occupied, &friendly);
         print_board(&board);
-        println!("");
+        println!();
         print_bitboard(moves);
         println!("{:#018x}", moves);
         moves
@@ -1080,205 +1058,718 @@ mod tests {
     }
 
     fn get_possible_moves_count(fen: &str, color: Color) -> usize {
-        let board = generate_board_from_fen(&fen.to_string()).unwrap();
-        let moves = get_possible_moves(&board, &color);
+        let board = generate_board_from_fen(fen).unwrap();
+        let moves = get_possible_moves(&board, &color, &None, &CastlingAvailability::default());
         moves.len()
     }
 
     #[test]
     fn test_pawn_move() {
-        let mut board = generate_board_from_fen(&"8/8/8/8/8/8/4P3/8".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/8/8/8/4P3/8").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "e4".to_string(), &Color::White);
+        let result = string_to_move(&board, "e4", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("e2"));
-        assert_eq!(mov.to, field_to_num("e4"));
+        assert_eq!(mov.from, square("e2"));
+        assert_eq!(mov.to, square("e4"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, None);
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Pawn);
     }
 
     #[test]
     fn test_knight_move() {
-        let mut board = generate_board_from_fen(&"8/8/8/8/8/8/8/6N1".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/8/8/8/8/6N1").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "Ne2".to_string(), &Color::White);
+        let result = string_to_move(&board, "Ne2", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("g1"));
-        assert_eq!(mov.to, field_to_num("e2"));
+        assert_eq!(mov.from, square("g1"));
+        assert_eq!(mov.to, square("e2"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, None);
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Knight);
     }
 
     #[test]
     fn test_capture_move() {
-        let mut board = generate_board_from_fen(&"8/8/3p4/4P3/8/8/8/8".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/3p4/4P3/8/8/8/8").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "xd6".to_string(), &Color::White);
+        let result = string_to_move(&board, "exd6", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("e5"));
-        assert_eq!(mov.to, field_to_num("d6"));
+        assert_eq!(mov.from, square("e5"));
+        assert_eq!(mov.to, square("d6"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, Some(Piece::Pawn));
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Pawn);
     }
 
+    #[test]
+    fn test_pawn_capture_without_file() {
+        let board = generate_board_from_fen("8/8/3p4/4P3/8/8/8/8").unwrap();
+        let result = string_to_move(&board, "xd6", &Color::White, &None, &CastlingAvailability::default());
+        assert_eq!(result, Err(MoveParseError::InvalidSyntax));
+    }
+
     #[test]
     fn test_knight_capture_move() {
-        let mut board = generate_board_from_fen(&"8/8/8/3p4/8/4N3/8/8".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/3p4/8/4N3/8/8").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "Nxd5".to_string(), &Color::White);
+        let result = string_to_move(&board, "Nxd5", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("e3"));
-        assert_eq!(mov.to, field_to_num("d5"));
+        assert_eq!(mov.from, square("e3"));
+        assert_eq!(mov.to, square("d5"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, Some(Piece::Pawn));
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Knight);
     }
 
     #[test]
     fn test_knight_disambiguation_file() {
-        let mut board = generate_board_from_fen(&"8/8/8/3N4/8/8/8/3N4".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/3N4/8/8/8/3N4").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "N5e3".to_string(), &Color::White);
+        let result = string_to_move(&board, "N5e3", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("d5"));
-        assert_eq!(mov.to, field_to_num("e3"));
+        assert_eq!(mov.from, square("d5"));
+        assert_eq!(mov.to, square("e3"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, None);
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Knight);
     }
 
     #[test]
     fn test_knight_disambiguation_rank() {
-        let mut board = generate_board_from_fen(&"8/8/8/8/2N3N1/8/8/8".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/8/2N3N1/8/8/8").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "Nce3".to_string(), &Color::White);
+        let result = string_to_move(&board, "Nce3", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("c4"));
-        assert_eq!(mov.to, field_to_num("e3"));
+        assert_eq!(mov.from, square("c4"));
+        assert_eq!(mov.to, square("e3"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, None);
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Knight);
     }
 
     #[test]
     fn test_knight_disambiguation_file_and_rank() {
-        let mut board = generate_board_from_fen(&"8/8/8/5N2/8/8/8/3N4".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/5N2/8/8/8/3N4").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "Nf5e3".to_string(), &Color::White);
+        let result = string_to_move(&board, "Nf5e3", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_ok());
         let mov = result.unwrap();
-        assert_eq!(mov.from, field_to_num("f5"));
-        assert_eq!(mov.to, field_to_num("e3"));
+        assert_eq!(mov.from, square("f5"));
+        assert_eq!(mov.to, square("e3"));
         assert_eq!(mov.promotion, None);
         assert_eq!(mov.capture, None);
-        assert_eq!(mov.castling, false);
+        assert!(!mov.castling);
         assert_eq!(mov.piece, Piece::Knight);
     }
 
     #[test]
     fn test_knight_nonsensical_move() {
-        let mut board = generate_board_from_fen(&"8/8/8/5N2/8/8/8/3N4".to_string()).unwrap();
+        let board = generate_board_from_fen("8/8/8/5N2/8/8/8/3N4").unwrap();
         print_board(&board);
-        let result = string_to_move(&mut board, "Nh1h2".to_string(), &Color::White);
+        let result = string_to_move(&board, "Nh1h2", &Color::White, &None, &CastlingAvailability::default());
         assert!(result.is_err());
     }
 
-    fn perft(depth: usize, board: &mut BitBoard, color: &Color) -> usize {
-        if depth == 0 {
-            return 1;
-        }
-        let mut nodes = 0;
-        let moves = get_possible_moves(&board, &color);
-        let opp_color = color.opposite();
-        for mov in moves {
-            board.apply_move(&mov, color);
-            let captures = get_capture_map(board, &opp_color);
-            let king = board.get_king_by_color(color);
-            let no_check = king & captures == 0;
-            if no_check {
-                nodes += perft(depth-1, board, &opp_color);
-            }
-            board.apply_move(&mov, color);
-        }
-        return nodes;
+    #[test]
+    fn test_enpassant_move() {
+        let board = generate_board_from_fen("8/8/8/3pP3/8/8/8/8").unwrap();
+        print_board(&board);
+        let result = string_to_move(&board, "exd6 e.p.", &Color::White, &Some(square("d6")), &CastlingAvailability::default());
+        assert!(result.is_ok());
+        let mov = result.unwrap();
+        assert_eq!(mov.from, square("e5"));
+        assert_eq!(mov.to, square("d6"));
+        assert_eq!(mov.promotion, None);
+        assert_eq!(mov.capture, Some(Piece::Pawn));
+        assert!(!mov.castling);
+        assert!(mov.enpassant);
+        assert_eq!(mov.piece, Piece::Pawn);
+    }
+
+    #[test]
+    fn test_enpassant_move_without_suffix() {
+        let board = generate_board_from_fen("8/8/8/8/3Pp3/8/8/8").unwrap();
+        print_board(&board);
+        let result = string_to_move(&board, "exd3", &Color::Black, &Some(square("d3")), &CastlingAvailability::default());
+        assert!(result.is_ok());
+        let mov = result.unwrap();
+        assert_eq!(mov.from, square("e4"));
+        assert_eq!(mov.to, square("d3"));
+        assert_eq!(mov.capture, Some(Piece::Pawn));
+        assert!(mov.enpassant);
     }
 
-    fn perft_divide(depth: usize, board: &mut BitBoard, color: &Color) -> usize {
-        assert!(depth > 0);
-        let mut nodes = 0;
-        let moves = get_possible_moves(board, color);
-        let opp_color = color.opposite();
+    #[test]
+    fn test_enpassant_move_without_target() {
+        let board = generate_board_from_fen("8/8/8/3pP3/8/8/8/8").unwrap();
+        print_board(&board);
+        let result = string_to_move(&board, "exd6", &Color::White, &None, &CastlingAvailability::default());
+        assert!(result.is_err());
+    }
 
-        for mov in moves {
-            board.apply_move(&mov, color);
-            let captures = get_capture_map(board, &opp_color);
-            let king = board.get_king_by_color(color);
-            let no_check = king & captures == 0;
-            if no_check {
-                let curr = perft(depth-1, board, &opp_color);
-                nodes += curr;
-                let from = format!("{}{}", num_to_file(mov.from), num_to_rank(mov.from));
-                let to = format!("{}{}", num_to_file(mov.to), num_to_rank(mov.to));
-                println!("Move: {}{}, Nodes: {}", from, to, curr);
+    #[test]
+    fn test_enpassant_suffix_on_regular_capture() {
+        let board = generate_board_from_fen("8/8/3p4/4P3/8/8/8/8").unwrap();
+        print_board(&board);
+        let result = string_to_move(&board, "exd6 e.p.", &Color::White, &None, &CastlingAvailability::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_kingside_castling_move() {
+        let fen = generate_bit_board("8/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let board = fen.board;
+        let result = string_to_move(&board, "O-O", &Color::White, &None, &fen.castling);
+        assert!(result.is_ok());
+        let mov = result.unwrap();
+        assert_eq!(mov.from, square("e1"));
+        assert_eq!(mov.to, square("g1"));
+        assert_eq!(mov.capture, None);
+        assert!(mov.castling);
+        assert_eq!(mov.piece, Piece::King);
+    }
+
+    #[test]
+    fn test_queenside_castling_move() {
+        let fen = generate_bit_board("r3k2r/8/8/8/8/8/8/8 b kq - 0 1").unwrap();
+        let board = fen.board;
+        let result = string_to_move(&board, "O-O-O+", &Color::Black, &None, &fen.castling);
+        assert!(result.is_ok());
+        let mov = result.unwrap();
+        assert_eq!(mov.from, square("e8"));
+        assert_eq!(mov.to, square("c8"));
+        assert!(mov.castling);
+        assert_eq!(mov.piece, Piece::King);
+    }
+
+    #[test]
+    fn test_castling_without_rights() {
+        let fen = generate_bit_board("8/8/8/8/8/8/8/R3K2R w Q - 0 1").unwrap();
+        let board = fen.board;
+        let result = string_to_move(&board, "O-O", &Color::White, &None, &fen.castling);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_castling_blocked() {
+        let fen = generate_bit_board("8/8/8/8/8/8/8/RN2K1NR w KQ - 0 1").unwrap();
+        let board = fen.board;
+        let moves = get_castling_moves(&board, &Color::White, &fen.castling);
+        assert_eq!(moves.len(), 0);
+    }
+
+    #[test]
+    fn test_castling_through_attacked_square() {
+        let fen = generate_bit_board("8/8/8/8/8/8/6p1/R3K2R w KQ - 0 1").unwrap();
+        let board = fen.board;
+        let moves = get_castling_moves(&board, &Color::White, &fen.castling);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].to, square("c1"));
+    }
+
+    #[test]
+    fn test_castling_out_of_check() {
+        let fen = generate_bit_board("4r3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let board = fen.board;
+        let moves = get_castling_moves(&board, &Color::White, &fen.castling);
+        assert_eq!(moves.len(), 0);
+    }
+
+    #[test]
+    fn test_queenside_castling_with_attacked_rook_path() {
+        let fen = generate_bit_board("1r6/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let board = fen.board;
+        let moves = get_castling_moves(&board, &Color::White, &fen.castling);
+        assert_eq!(moves.len(), 2);
+    }
+
+    #[test]
+    fn test_castling_move_to_string() {
+        let board = generate_board_from_fen("3k4/8/8/8/8/8/8/R3K2R").unwrap();
+        let mov = Move { from: square("e1"), to: square("g1"), promotion: None, capture: None, castling: true, enpassant: false, piece: Piece::King };
+        assert_eq!(move_to_string(&board, &mov, &Color::White), "O-O");
+        let mov = Move { from: square("e1"), to: square("c1"), promotion: None, capture: None, castling: true, enpassant: false, piece: Piece::King };
+        assert_eq!(move_to_string(&board, &mov, &Color::White), "O-O-O+");
+    }
+
+    fn san(fen: &str, lan: &str) -> String {
+        let fen = generate_bit_board(fen).unwrap();
+        let board = fen.board;
+        let mov = string_to_move(&board, lan, &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        move_to_string(&board, &mov, &fen.color)
+    }
+
+    #[test]
+    fn test_move_to_string_disambiguation() {
+        assert_eq!(san("rnbqkb1r/ppp1pppp/8/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "b8d7"), "Nd7");
+        assert_eq!(san("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "b8d7"), "Nbd7");
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        assert_eq!(san("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1"), "Qh4e1");
+        assert_eq!(san("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "e4e1"), "Qee1");
+    }
+
+    #[test]
+    fn test_move_to_string_ignores_pinned_pieces() {
+        // knight on c3 is pinned, so it doesn't make Ng1-e2 ambiguous
+        assert_eq!(san("4k3/8/8/b7/8/2N5/8/4K1N1 w - - 0 1", "g1e2"), "Ne2");
+    }
+
+    #[test]
+    fn test_move_to_string_pawn_moves() {
+        assert_eq!(san("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+        assert_eq!(san("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q"), "axb8=Q+");
+        assert_eq!(san("4k3/6P1/8/8/8/8/8/4K3 w - - 0 1", "g7g8n"), "g8=N");
+    }
+
+    #[test]
+    fn test_move_to_string_check_and_mate() {
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san("6k1/6pp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8+");
+        // discovered check by a castling rook is still a check
+        assert_eq!(san("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), "O-O+");
+    }
+
+    #[test]
+    fn test_move_to_string_enpassant_escapes_mate() {
+        // only reply to the check is capturing the checking pawn enpassant
+        assert_eq!(san("3R4/8/8/4k3/4p3/2P4Q/3P4/5RK1 w - - 0 1", "d2d4"), "d4+");
+    }
+
+    #[test]
+    fn test_enpassant_moves_generated() {
+        let board = generate_board_from_fen("8/8/8/2PpP3/8/8/8/8").unwrap();
+        let moves = get_possible_moves(&board, &Color::White, &Some(square("d6")), &CastlingAvailability::default());
+        let enpassant: Vec<&Move> = moves.iter().filter(|m| m.enpassant).collect();
+        assert_eq!(enpassant.len(), 2);
+        assert!(enpassant.iter().all(|m| m.to == square("d6") && m.capture == Some(Piece::Pawn)));
+    }
+
+    #[test]
+    fn test_enpassant_square_after_double_push() {
+        let mov = Move { from: square("e2"), to: square("e4"), promotion: None, capture: None, castling: false, enpassant: false, piece: Piece::Pawn };
+        assert_eq!(mov.enpassant_square(), Some(square("e3")));
+        let mov = Move { from: square("d7"), to: square("d5"), promotion: None, capture: None, castling: false, enpassant: false, piece: Piece::Pawn };
+        assert_eq!(mov.enpassant_square(), Some(square("d6")));
+        let mov = Move { from: square("e2"), to: square("e3"), promotion: None, capture: None, castling: false, enpassant: false, piece: Piece::Pawn };
+        assert_eq!(mov.enpassant_square(), None);
+        let mov = Move { from: square("a1"), to: square("a3"), promotion: None, capture: None, castling: false, enpassant: false, piece: Piece::Rook };
+        assert_eq!(mov.enpassant_square(), None);
+    }
+
+    #[test]
+    fn test_enpassant_move_to_string() {
+        let board = generate_board_from_fen("8/8/8/3pP3/8/8/8/8").unwrap();
+        let mov = Move { from: square("e5"), to: square("d6"), promotion: None, capture: Some(Piece::Pawn), castling: false, enpassant: true, piece: Piece::Pawn };
+        assert_eq!(move_to_string(&board, &mov, &Color::White), "exd6");
+    }
+
+    fn legal_lan(fen: &str) -> Vec<String> {
+        let fen = generate_bit_board(fen).unwrap();
+        let mut moves: Vec<String> = get_legal_moves(&fen.board, &fen.color, &fen.enpassant, &fen.castling).iter().map(move_to_lan).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn test_legal_moves_in_double_check() {
+        // rook on e8 and knight on f3 both give check, so the queen can't take the knight
+        assert_eq!(legal_lan("4r1k1/8/8/8/8/5n2/3P4/3QKB2 w - - 0 1"), vec!["e1f2"]);
+    }
+
+    #[test]
+    fn test_legal_moves_block_or_capture_check() {
+        // bishop on b4 checks, so only interposing or moving the king is allowed
+        assert_eq!(legal_lan("4k3/8/8/8/1b6/8/8/RN2K3 w Q - 0 1"), vec!["b1c3", "b1d2", "e1d1", "e1e2", "e1f1", "e1f2"]);
+        assert_eq!(legal_lan("4k3/8/8/8/1b6/8/2N5/R3K3 w Q - 0 1"), vec!["c2b4", "e1d1", "e1e2", "e1f1", "e1f2"]);
+    }
+
+    #[test]
+    fn test_pinned_pieces_move_along_pin() {
+        // rook on e4 is pinned by the rook on e8 and bishop on d2 by the queen on a5
+        let moves = legal_lan("4r1k1/8/8/q7/4R3/8/3B4/4K3 w - - 0 1");
+        assert!(moves.contains(&"e4e8".to_string()));
+        assert!(moves.contains(&"e4e5".to_string()));
+        assert!(!moves.contains(&"e4d4".to_string()));
+        assert!(moves.contains(&"d2a5".to_string()));
+        assert!(moves.contains(&"d2c3".to_string()));
+        assert!(!moves.contains(&"d2e3".to_string()));
+    }
+
+    #[test]
+    fn test_enpassant_discovering_check_on_rank() {
+        assert!(!legal_lan("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").contains(&"b5c6".to_string()));
+        assert!(legal_lan("8/8/8/KPp5/8/8/8/7k w - c6 0 1").contains(&"b5c6".to_string()));
+    }
+
+    #[test]
+    fn test_legal_moves_match_filtered_pseudo_legal_moves() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        ];
+        for fen in fens {
+            let fen = generate_bit_board(fen).unwrap();
+            let mut board = fen.board;
+            for mov in get_legal_moves(&board, &fen.color, &fen.enpassant, &fen.castling) {
+                let color = fen.color.opposite();
+                board.apply_move(&mov, &fen.color);
+                let enpassant = mov.enpassant_square();
+                let castling = fen.castling.after_move(&mov, &fen.color);
+                let mut expected: Vec<String> = get_possible_moves(&board, &color, &enpassant, &castling)
+                    .into_iter()
+                    .filter(|reply| is_legal(&mut board.clone(), reply, &color))
+                    .map(|reply| move_to_lan(&reply))
+                    .collect();
+                let mut legal: Vec<String> = get_legal_moves(&board, &color, &enpassant, &castling).iter().map(move_to_lan).collect();
+                expected.sort();
+                legal.sort();
+                assert_eq!(legal, expected);
+                board.undo_move(&mov, &fen.color);
             }
-            board.apply_move(&mov, color);
         }
-        return nodes;
     }
 
     #[test]
     fn test_perft1_for_initial_position() {
-        let mut board = generate_board_from_fen(&"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
-        let perft = perft(1, &mut board, &Color::White);
+        let mut board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let perft = perft(1, &mut board, &Color::White, &None, &CastlingAvailability::default());
         let target = 20;
         assert_eq!(perft, target, "perft1 should be {}, but is {}", target, perft);
     }
 
     #[test]
     fn test_perft2_for_initial_position() {
-        let mut board = generate_board_from_fen(&"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
-        let perft = perft(2, &mut board, &Color::White);
+        let mut board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let perft = perft(2, &mut board, &Color::White, &None, &CastlingAvailability::default());
         let target = 400;
         assert_eq!(perft, target, "perft2 should be {}, but is {}", target, perft);
     }
 
     #[test]
     fn test_perft3_for_initial_position() {
-        let mut board = generate_board_from_fen(&"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
-        let perft = perft(3, &mut board, &Color::White);
+        let mut board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let perft = perft(3, &mut board, &Color::White, &None, &CastlingAvailability::default());
         let target = 8902;
         assert_eq!(perft, target, "perft3 should be {}, but is {}", target, perft);
     }
 
     #[test]
     fn test_perft4_for_initial_position() {
-        let mut board = generate_board_from_fen(&"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
-        let perft = perft_divide(4, &mut board, &Color::White);
+        let mut board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let divide = perft_divide(4, &mut board, &Color::White, &None, &CastlingAvailability::default());
+        for (mov, nodes) in &divide {
+            println!("Move: {}, Nodes: {}", move_to_lan(mov), nodes);
+        }
+        let perft: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
         let target = 197281;
         assert_eq!(perft, target, "perft4 should be {}, but is {}", target, perft);
     }
 
     #[test]
-    #[ignore = "enpassant is not yet implemented"]
     fn test_perft5_for_initial_position() {
-        let mut board = generate_board_from_fen(&"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
-        let perft = perft(5, &mut board, &Color::White);
+        let mut board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let perft = perft(5, &mut board, &Color::White, &None, &CastlingAvailability::default());
         let target = 4865609 ;
         assert_eq!(perft, target, "perft5 should be {}, but is {}", target, perft);
     }
+
+    #[test]
+    fn test_perft4_for_enpassant_position() {
+        let fen = generate_bit_board("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+        let mut board = fen.board;
+        let perft = perft(4, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 43238;
+        assert_eq!(perft, target, "perft4 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_perft3_with_enpassant_target() {
+        let fen = generate_bit_board("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w - f6 0 3").unwrap();
+        let mut board = fen.board;
+        let perft = perft(3, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 21637;
+        assert_eq!(perft, target, "perft3 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_perft3_for_kiwipete() {
+        let fen = generate_bit_board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut board = fen.board;
+        let perft = perft(3, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 97862;
+        assert_eq!(perft, target, "perft3 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_perft4_for_kiwipete() {
+        let fen = generate_bit_board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut board = fen.board;
+        let perft = perft(4, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 4085603;
+        assert_eq!(perft, target, "perft4 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_perft3_for_promotion_position() {
+        let fen = generate_bit_board("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1").unwrap();
+        let mut board = fen.board;
+        let perft = perft(3, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 9467;
+        assert_eq!(perft, target, "perft3 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_perft3_for_castling_position() {
+        let fen = generate_bit_board("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
+        let mut board = fen.board;
+        let perft = perft(3, &mut board, &fen.color, &fen.enpassant, &fen.castling);
+        let target = 62379;
+        assert_eq!(perft, target, "perft3 should be {}, but is {}", target, perft);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let fens = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 b - - 0 1",
+            "4k3/8/8/8/8/8/8/4KB2 b - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 b - - 0 1",
+        ];
+        for fen in fens {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(game_state(&position, &[]).unwrap(), GameState::InsufficientMaterial, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_sufficient_material() {
+        let fens = [
+            "4k3/8/8/8/8/8/8/2B1KB2 b - - 0 1",
+            "2b1k3/8/8/8/8/8/8/2B1K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/3NKN2 b - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KB2 b - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/4KR2 b - - 0 1",
+        ];
+        for fen in fens {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(game_state(&position, &[]).unwrap(), GameState::Normal, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_dead_position_with_blocked_pawns() {
+        let position = Position::from_fen("8/4k3/8/p1p1p1p1/P1P1P1P1/8/4K3/8 w - - 0 40").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::DeadPosition);
+    }
+
+    #[test]
+    fn test_blocked_pawns_with_open_file() {
+        let position = Position::from_fen("8/4k3/8/p1p1p3/P1P1P3/8/4K3/8 w - - 0 40").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::Normal);
+    }
+
+    #[test]
+    fn test_blocked_pawns_with_possible_capture() {
+        let position = Position::from_fen("8/4k3/8/p1p1pp2/P1P1PP2/8/4K3/8 w - - 0 40").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::Normal);
+        let position = Position::from_fen("8/4k3/8/p1p1p1p1/P1P1P1P1/8/4K3/7B w - - 0 40").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::Normal);
+    }
+
+    #[test]
+    fn test_fifty_moves() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 99 60").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::Normal);
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 100 60").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::FiftyMoves);
+    }
+
+    #[test]
+    fn test_seventy_five_moves() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 150 85").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::SeventyFiveMoves);
+    }
+
+    #[test]
+    fn test_checkmate_takes_precedence_over_fifty_moves() {
+        let position = Position::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 100 3").unwrap();
+        assert_eq!(game_state(&position, &[]).unwrap(), GameState::Checkmate);
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let history = vec![
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+            "rnbqkbnr/pppppppp/5n2/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2".to_string(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 3".to_string(),
+        ];
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 8 5").unwrap();
+        assert_eq!(game_state(&position, &history[..2]).unwrap(), GameState::Normal);
+        assert_eq!(game_state(&position, &history).unwrap(), GameState::ThreefoldRepetition);
+    }
+
+    #[test]
+    fn test_fivefold_repetition() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b - - 20 40".to_string();
+        let history = vec![fen.clone(); 4];
+        let position = Position::from_fen(&fen).unwrap();
+        assert_eq!(game_state(&position, &history).unwrap(), GameState::FivefoldRepetition);
+    }
+
+    #[test]
+    fn test_invalid_history() {
+        let history = vec![
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 2 2".to_string(),
+        ];
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 3").unwrap();
+        assert!(game_state(&position, &history).is_err());
+    }
+
+    #[test]
+    fn test_repetition_with_different_castling_rights() {
+        let history = vec![
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".to_string(),
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 4 3".to_string(),
+        ];
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w kq - 8 5").unwrap();
+        assert_eq!(game_state(&position, &history).unwrap(), GameState::Normal);
+    }
+
+    #[test]
+    fn test_repetition_ignores_impossible_enpassant() {
+        let history = vec![
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 4 3".to_string(),
+        ];
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 8 5").unwrap();
+        assert_eq!(game_state(&position, &history).unwrap(), GameState::ThreefoldRepetition);
+    }
+
+    #[test]
+    fn test_repetition_with_possible_enpassant() {
+        let history = vec![
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3".to_string(),
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 4 5".to_string(),
+        ];
+        let position = Position::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 8 7").unwrap();
+        assert_eq!(game_state(&position, &history).unwrap(), GameState::Normal);
+    }
+
+    #[test]
+    fn test_coordinate_moves() {
+        let fen = generate_bit_board("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1").unwrap();
+        let board = fen.board;
+        let mov = string_to_move(&board, "b2a1n", &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        assert_eq!(mov.capture, Some(Piece::Rook));
+        assert_eq!(mov.promotion, Some(Piece::Knight));
+        assert_eq!(move_to_lan(&mov), "b2a1n");
+        let mov = string_to_move(&board, "e8c8", &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        assert!(mov.castling);
+        assert_eq!(move_to_lan(&mov), "e8c8");
+        let mov = string_to_move(&board, "b2xa1=Q", &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        assert_eq!(mov.promotion, Some(Piece::Queen));
+    }
+
+    #[test]
+    fn test_move_notations() {
+        let fen = generate_bit_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let board = fen.board;
+        for (notation, expected) in [("e4!?", "e2e4"), ("e2-e4", "e2e4"), ("e2e4", "e2e4"), ("Nf3+", "g1f3"), ("Ng1f3", "g1f3"), ("Ng1-f3", "g1f3"), ("g1f3", "g1f3")] {
+            let mov = string_to_move(&board, notation, &fen.color, &fen.enpassant, &fen.castling).unwrap();
+            assert_eq!(move_to_lan(&mov), expected);
+        }
+        let fen = generate_bit_board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let board = fen.board;
+        assert!(string_to_move(&board, "0-0", &fen.color, &fen.enpassant, &fen.castling).unwrap().castling);
+        assert!(string_to_move(&board, "0-0-0#", &fen.color, &fen.enpassant, &fen.castling).unwrap().castling);
+        assert!(string_to_move(&board, "e1g1", &fen.color, &fen.enpassant, &fen.castling).unwrap().castling);
+        assert_eq!(string_to_move(&board, "Kg1", &fen.color, &fen.enpassant, &fen.castling).err(), Some(MoveParseError::Illegal));
+    }
+
+    #[test]
+    fn test_coordinate_capture_without_flag() {
+        let board = generate_board_from_fen("8/8/3p4/4P3/8/8/8/8").unwrap();
+        let mov = string_to_move(&board, "e5d6", &Color::White, &None, &CastlingAvailability::default()).unwrap();
+        assert_eq!(mov.capture, Some(Piece::Pawn));
+        assert_eq!(string_to_move(&board, "e5-d6", &Color::White, &None, &CastlingAvailability::default()).err(), Some(MoveParseError::WrongCaptureFlag));
+    }
+
+    #[test]
+    fn test_move_parse_errors() {
+        let fen = generate_bit_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let board = fen.board;
+        let parse = |mov: &str| string_to_move(&board, mov, &fen.color, &fen.enpassant, &fen.castling).err();
+        assert_eq!(parse("xxNf3garbage"), Some(MoveParseError::InvalidSyntax));
+        assert_eq!(parse("Nf3garbage"), Some(MoveParseError::InvalidSyntax));
+        assert_eq!(parse(""), Some(MoveParseError::InvalidSyntax));
+        assert_eq!(parse("Xf3"), Some(MoveParseError::UnknownPiece('X')));
+        assert_eq!(parse("e8=X"), Some(MoveParseError::UnknownPiece('X')));
+        assert_eq!(parse("Nxf3"), Some(MoveParseError::WrongCaptureFlag));
+        assert_eq!(parse("e2e5"), Some(MoveParseError::Illegal));
+        assert_eq!(parse("e2e4q"), Some(MoveParseError::Illegal));
+        assert_eq!(parse("O-O"), Some(MoveParseError::Illegal));
+        assert_eq!(parse("Qd4"), Some(MoveParseError::Illegal));
+        assert_eq!(parse("e3d4"), Some(MoveParseError::NoSuchPiece));
+
+        let board = generate_board_from_fen("8/8/8/3N4/8/8/8/3N4").unwrap();
+        assert_eq!(string_to_move(&board, "Ne3", &Color::White, &None, &CastlingAvailability::default()).err(), Some(MoveParseError::Ambiguous));
+        assert_eq!(string_to_move(&board, "Be3", &Color::White, &None, &CastlingAvailability::default()).err(), Some(MoveParseError::NoSuchPiece));
+    }
+
+    #[test]
+    fn test_pinned_piece_is_not_ambiguous() {
+        let fen = generate_bit_board("4k3/8/8/b7/8/2N5/8/4K1N1 w - - 0 1").unwrap();
+        let board = fen.board;
+        let mov = string_to_move(&board, "Ne2", &fen.color, &fen.enpassant, &fen.castling).unwrap();
+        assert_eq!(mov.from, square("g1"));
+        assert_eq!(string_to_move(&board, "Nce2", &fen.color, &fen.enpassant, &fen.castling).err(), Some(MoveParseError::Illegal));
+    }
+
+    #[test]
+    fn test_move_json() {
+        let board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let mov = string_to_move(&board, "e4", &Color::White, &None, &CastlingAvailability::default()).unwrap();
+        let json = serde_json::to_string(&mov).unwrap();
+        assert_eq!(json, r#"{"from":"e2","to":"e4","promotion":null,"piece":"pawn"}"#);
+        assert_eq!(serde_json::from_str::<Move>(&json).unwrap(), mov);
+
+        let fen = generate_bit_board("r3k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let moves = get_legal_moves(&fen.board, &fen.color, &fen.enpassant, &fen.castling);
+        for mov in moves {
+            let json = serde_json::to_string(&mov).unwrap();
+            assert_eq!(serde_json::from_str::<Move>(&json).unwrap(), mov);
+        }
+
+        assert!(serde_json::from_str::<Move>(r#"{"from":"e9","to":"e4","promotion":null,"piece":"pawn"}"#).is_err());
+        assert!(serde_json::from_str::<Move>(r#"{"from":"e2","to":"e4","promotion":"pope","piece":"pawn"}"#).is_err());
+        assert!(serde_json::from_str::<Move>(r#"{"from":"g1","to":"f3","promotion":null}"#).is_err());
+    }
+
+    #[test]
+    fn test_moves_in_set() {
+        let board = generate_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let moves = get_legal_moves(&board, &Color::White, &None, &CastlingAvailability::default());
+        let mut set: std::collections::HashSet<Move> = moves.iter().copied().collect();
+        assert_eq!(set.len(), 20);
+        set.extend(moves);
+        assert_eq!(set.len(), 20);
+    }
 }
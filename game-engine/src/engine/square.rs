@@ -0,0 +1,422 @@
+use std::{fmt, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not}, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// bit 0 is h1 and bit 63 is a8, so within a rank the bits go from the h file to the a file
+const FILE_A: u64 = 0x8080_8080_8080_8080;
+const FILE_H: u64 = 0x0101_0101_0101_0101;
+const RANK_1: u64 = 0x0000_0000_0000_00FF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct File(u8);
+
+impl File {
+    pub const A: File = File(0);
+    pub const B: File = File(1);
+    pub const C: File = File(2);
+    pub const D: File = File(3);
+    pub const E: File = File(4);
+    pub const F: File = File(5);
+    pub const G: File = File(6);
+    pub const H: File = File(7);
+    pub const ALL: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+
+    pub fn from_char(file: char) -> Option<File> {
+        match file {
+            'a'..='h' => Some(File(file as u8 - b'a')),
+            _ => None,
+        }
+    }
+
+    // 0 for the a file
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+
+    pub fn bitboard(self) -> Bitboard {
+        Bitboard(FILE_A >> self.0)
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rank(u8);
+
+impl Rank {
+    pub const FIRST: Rank = Rank(0);
+    pub const SECOND: Rank = Rank(1);
+    pub const THIRD: Rank = Rank(2);
+    pub const FOURTH: Rank = Rank(3);
+    pub const FIFTH: Rank = Rank(4);
+    pub const SIXTH: Rank = Rank(5);
+    pub const SEVENTH: Rank = Rank(6);
+    pub const EIGHTH: Rank = Rank(7);
+    pub const ALL: [Rank; 8] = [Rank::FIRST, Rank::SECOND, Rank::THIRD, Rank::FOURTH, Rank::FIFTH, Rank::SIXTH, Rank::SEVENTH, Rank::EIGHTH];
+
+    pub fn from_char(rank: char) -> Option<Rank> {
+        match rank {
+            '1'..='8' => Some(Rank(rank as u8 - b'1')),
+            _ => None,
+        }
+    }
+
+    // 0 for the first rank
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+
+    pub fn bitboard(self) -> Bitboard {
+        Bitboard(RANK_1 << (self.0 * 8))
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+// index of the bit in a bitboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(file: File, rank: Rank) -> Square {
+        Square(rank.0 * 8 + 7 - file.0)
+    }
+
+    pub fn from_index(index: u8) -> Option<Square> {
+        match index < 64 {
+            true => Some(Square(index)),
+            false => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> File {
+        File(7 - self.0 % 8)
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+
+    pub fn bitboard(self) -> Bitboard {
+        Bitboard(1 << self.0)
+    }
+
+    // none if the square would be off the board
+    pub fn offset(self, files: i8, ranks: i8) -> Option<Square> {
+        let file = self.file().0 as i8 + files;
+        let rank = self.rank().0 as i8 + ranks;
+        match (0..8).contains(&file) && (0..8).contains(&rank) {
+            true => Some(Square::new(File(file as u8), Rank(rank as u8))),
+            false => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SquareParseError {
+    pub square: String,
+}
+
+impl fmt::Display for SquareParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid square '{}'", self.square)
+    }
+}
+
+impl std::error::Error for SquareParseError {}
+
+impl FromStr for Square {
+    type Err = SquareParseError;
+
+    fn from_str(square: &str) -> Result<Square, SquareParseError> {
+        let mut chars = square.chars();
+        match (chars.next().and_then(File::from_char), chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(file), Some(rank), None) => Ok(Square::new(file, rank)),
+            _ => Err(SquareParseError { square: square.to_string() }),
+        }
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+impl Serialize for Square {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Square {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Square, D::Error> {
+        let square = String::deserialize(deserializer)?;
+        square.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North, South, East, West,
+    NorthEast, NorthWest, SouthEast, SouthWest,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::North, Direction::South, Direction::East, Direction::West,
+        Direction::NorthEast, Direction::NorthWest, Direction::SouthEast, Direction::SouthWest,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(u64::MAX);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, square: Square) -> bool {
+        self.0 & 1 << square.0 != 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    // square of the lowest set bit
+    pub fn first(self) -> Option<Square> {
+        match self.0 {
+            0 => None,
+            bits => Some(Square(bits.trailing_zeros() as u8)),
+        }
+    }
+
+    // pieces shifted off the board are dropped instead of wrapping to the other side
+    pub fn shift(self, direction: Direction) -> Bitboard {
+        let bits = self.0;
+        Bitboard(match direction {
+            Direction::North => bits << 8,
+            Direction::South => bits >> 8,
+            Direction::East => (bits >> 1) & !FILE_A,
+            Direction::West => (bits << 1) & !FILE_H,
+            Direction::NorthEast => (bits << 7) & !FILE_A,
+            Direction::NorthWest => (bits << 9) & !FILE_H,
+            Direction::SouthEast => (bits >> 9) & !FILE_A,
+            Direction::SouthWest => (bits >> 7) & !FILE_H,
+        })
+    }
+}
+
+impl From<Square> for Bitboard {
+    fn from(square: Square) -> Bitboard {
+        square.bitboard()
+    }
+}
+
+impl PartialEq<u64> for Bitboard {
+    fn eq(&self, other: &u64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::LowerHex for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 & other.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 | other.0)
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 ^ other.0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, other: Bitboard) {
+        self.0 &= other.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, other: Bitboard) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, other: Bitboard) {
+        self.0 ^= other.0;
+    }
+}
+
+// squares of the set bits, from h1 to a8
+pub struct Squares(u64);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let square = Square(self.0.trailing_zeros() as u8);
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    fn into_iter(self) -> Squares {
+        Squares(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_square_layout() {
+        assert_eq!(square("h1").index(), 0);
+        assert_eq!(square("a1").index(), 7);
+        assert_eq!(square("e2").index(), 11);
+        assert_eq!(square("a8").index(), 63);
+        assert_eq!(Square::new(File::E, Rank::FOURTH), square("e4"));
+        assert_eq!(square("c7").file(), File::C);
+        assert_eq!(square("c7").rank(), Rank::SEVENTH);
+    }
+
+    #[test]
+    fn test_square_parse_and_display() {
+        for index in 0..64 {
+            let square = Square::from_index(index).unwrap();
+            assert_eq!(square.to_string().parse::<Square>().unwrap(), square);
+        }
+        assert_eq!(square("g7").to_string(), "g7");
+        assert_eq!(Square::from_index(64), None);
+        for invalid in ["", "e", "e9", "i1", "E2", "e2e4", "22"] {
+            assert_eq!(invalid.parse::<Square>(), Err(SquareParseError { square: invalid.to_string() }));
+        }
+    }
+
+    #[test]
+    fn test_square_offset() {
+        assert_eq!(square("e2").offset(0, 2), Some(square("e4")));
+        assert_eq!(square("b1").offset(-1, 2), Some(square("a3")));
+        assert_eq!(square("b1").offset(-2, 1), None);
+        assert_eq!(square("h4").offset(1, 0), None);
+        assert_eq!(square("d8").offset(0, 1), None);
+    }
+
+    #[test]
+    fn test_shift_doesnt_wrap() {
+        let edges = File::A.bitboard() | File::H.bitboard() | Rank::FIRST.bitboard() | Rank::EIGHTH.bitboard();
+        for direction in Direction::ALL {
+            for square in edges {
+                let expected = match direction {
+                    Direction::North => square.offset(0, 1),
+                    Direction::South => square.offset(0, -1),
+                    Direction::East => square.offset(1, 0),
+                    Direction::West => square.offset(-1, 0),
+                    Direction::NorthEast => square.offset(1, 1),
+                    Direction::NorthWest => square.offset(-1, 1),
+                    Direction::SouthEast => square.offset(1, -1),
+                    Direction::SouthWest => square.offset(-1, -1),
+                };
+                assert_eq!(square.bitboard().shift(direction).first(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterate_squares() {
+        let bitboard = square("a1").bitboard() | square("e4").bitboard() | square("h8").bitboard();
+        let squares: Vec<String> = bitboard.into_iter().map(|square| square.to_string()).collect();
+        assert_eq!(squares, vec!["a1", "e4", "h8"]);
+        assert_eq!(bitboard.count(), 3);
+        assert!(bitboard.contains(square("e4")));
+        assert!(!bitboard.contains(square("e5")));
+        assert_eq!(Bitboard::EMPTY.into_iter().next(), None);
+    }
+
+    #[test]
+    fn test_file_and_rank_bitboards() {
+        assert_eq!(File::A.bitboard(), FILE_A);
+        assert_eq!(File::H.bitboard(), FILE_H);
+        assert_eq!(Rank::FIRST.bitboard(), RANK_1);
+        assert_eq!(Rank::EIGHTH.bitboard(), 0xFF00_0000_0000_0000);
+        assert_eq!(File::from_char('c'), Some(File::C));
+        assert_eq!(Rank::from_char('9'), None);
+    }
+
+    #[test]
+    fn test_square_json() {
+        assert_eq!(serde_json::to_string(&square("e2")).unwrap(), "\"e2\"");
+        assert_eq!(serde_json::from_str::<Square>("\"e2\"").unwrap(), square("e2"));
+        assert!(serde_json::from_str::<Square>("\"z9\"").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use super::rules::Move;
+
+pub const MATE: i32 = 1_000_000;
+pub const MATE_THRESHOLD: i32 = MATE - 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<Move>,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    // size in megabytes, number of entries is rounded down to a power of two
+    pub fn new(size: usize) -> TranspositionTable {
+        let count = (size * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+        let count = match count.is_power_of_two() {
+            true => count,
+            false => count.next_power_of_two() / 2,
+        };
+        TranspositionTable {
+            entries: vec![None; count],
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.entries.len() - 1)
+    }
+
+    // entries for other positions are always replaced, entries for the same position only by deeper searches
+    pub fn store(&mut self, hash: u64, depth: u8, bound: Bound, score: i32, best_move: Option<Move>, ply: i32) {
+        let index = self.index(hash);
+        if let Some(entry) = &self.entries[index] {
+            if entry.hash == hash && entry.depth > depth {
+                return;
+            }
+        }
+        self.entries[index] = Some(Entry {
+            hash,
+            depth,
+            bound,
+            score: score_to_table(score, ply),
+            best_move,
+        });
+    }
+
+    pub fn probe(&self, hash: u64, ply: i32) -> Option<Entry> {
+        match self.entries[self.index(hash)] {
+            Some(entry) if entry.hash == hash => Some(Entry {
+                score: score_from_table(entry.score, ply),
+                ..entry
+            }),
+            _ => None,
+        }
+    }
+}
+
+// mate scores are counted from the root in search, but from the stored position in the table
+fn score_to_table(score: i32, ply: i32) -> i32 {
+    match score {
+        score if score > MATE_THRESHOLD => score + ply,
+        score if score < -MATE_THRESHOLD => score - ply,
+        score => score,
+    }
+}
+
+fn score_from_table(score: i32, ply: i32) -> i32 {
+    match score {
+        score if score > MATE_THRESHOLD => score - ply,
+        score if score < -MATE_THRESHOLD => score + ply,
+        score => score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_is_power_of_two() {
+        let table = TranspositionTable::new(1);
+        assert!(table.entries.len().is_power_of_two());
+        assert!(table.entries.len() * std::mem::size_of::<Option<Entry>>() <= 1024 * 1024);
+    }
+
+    #[test]
+    fn test_store_and_probe() {
+        let mut table = TranspositionTable::new(1);
+        table.store(42, 3, Bound::Lower, 120, None, 2);
+        let entry = table.probe(42, 5).unwrap();
+        assert_eq!(entry.depth, 3);
+        assert_eq!(entry.bound, Bound::Lower);
+        assert_eq!(entry.score, 120);
+        assert!(table.probe(43, 2).is_none());
+    }
+
+    #[test]
+    fn test_mate_score_adjustment() {
+        let mut table = TranspositionTable::new(1);
+        // mated 3 plies below a position stored at ply 4
+        table.store(7, 3, Bound::Exact, -MATE + 7, None, 4);
+        assert_eq!(table.probe(7, 4).unwrap().score, -MATE + 7);
+        assert_eq!(table.probe(7, 1).unwrap().score, -MATE + 4);
+        table.store(8, 3, Bound::Exact, MATE - 7, None, 4);
+        assert_eq!(table.probe(8, 2).unwrap().score, MATE - 5);
+    }
+
+    #[test]
+    fn test_deeper_entry_is_kept() {
+        let mut table = TranspositionTable::new(1);
+        table.store(42, 5, Bound::Exact, 10, None, 0);
+        table.store(42, 2, Bound::Exact, 20, None, 0);
+        assert_eq!(table.probe(42, 0).unwrap().score, 10);
+        table.store(42, 5, Bound::Exact, 20, None, 0);
+        assert_eq!(table.probe(42, 0).unwrap().score, 20);
+    }
+}
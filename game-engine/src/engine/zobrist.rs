@@ -0,0 +1,171 @@
+use once_cell::sync::Lazy;
+
+use crate::Color;
+
+use super::{get_castling_rook_squares, get_enpassant_capture, rules::{Move, Piece}, square::Square, BitBoard, CastlingAvailability};
+
+struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    black_to_move: u64,
+    castling: [u64; 4],
+    enpassant: [u64; 8],
+}
+
+// keys are generated from a fixed seed, so hashes are the same between runs
+static KEYS: Lazy<ZobristKeys> = Lazy::new(|| {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut next = || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+
+    let mut pieces = [[0; 64]; 12];
+    for piece in pieces.iter_mut() {
+        for key in piece.iter_mut() {
+            *key = next();
+        }
+    }
+    let black_to_move = next();
+    let mut castling = [0; 4];
+    for key in castling.iter_mut() {
+        *key = next();
+    }
+    let mut enpassant = [0; 8];
+    for key in enpassant.iter_mut() {
+        *key = next();
+    }
+    ZobristKeys { pieces, black_to_move, castling, enpassant }
+});
+
+fn piece_index(piece: &Piece, color: &Color) -> usize {
+    let index = match piece {
+        Piece::Pawn => 0,
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        Piece::King => 5,
+    };
+    match color {
+        Color::White => index,
+        Color::Black => index + 6,
+    }
+}
+
+pub fn get_hash(board: &BitBoard, color: &Color, castling: &CastlingAvailability, enpassant: &Option<Square>) -> u64 {
+    let pieces = [
+        (board.white_pawns, Piece::Pawn, Color::White),
+        (board.white_knights, Piece::Knight, Color::White),
+        (board.white_bishops, Piece::Bishop, Color::White),
+        (board.white_rooks, Piece::Rook, Color::White),
+        (board.white_queens, Piece::Queen, Color::White),
+        (board.white_king, Piece::King, Color::White),
+        (board.black_pawns, Piece::Pawn, Color::Black),
+        (board.black_knights, Piece::Knight, Color::Black),
+        (board.black_bishops, Piece::Bishop, Color::Black),
+        (board.black_rooks, Piece::Rook, Color::Black),
+        (board.black_queens, Piece::Queen, Color::Black),
+        (board.black_king, Piece::King, Color::Black),
+    ];
+
+    let mut hash = 0;
+    for (bitboard, piece, piece_color) in pieces {
+        let index = piece_index(&piece, &piece_color);
+        for square in bitboard {
+            hash ^= KEYS.pieces[index][square.index()];
+        }
+    }
+    if color == &Color::Black {
+        hash ^= KEYS.black_to_move;
+    }
+    hash ^ hash_castling(castling) ^ hash_enpassant(enpassant)
+}
+
+// changes of pieces and side to move; applying it twice restores the hash
+pub fn hash_move(mov: &Move, color: &Color) -> u64 {
+    let moved = piece_index(&mov.piece, color);
+    let placed = match mov.promotion {
+        Some(promotion) => piece_index(&promotion, color),
+        None => moved,
+    };
+    let mut hash = KEYS.black_to_move ^ KEYS.pieces[moved][mov.from.index()] ^ KEYS.pieces[placed][mov.to.index()];
+
+    if let Some(capture) = mov.capture {
+        let square = match mov.enpassant {
+            true => get_enpassant_capture(mov),
+            false => mov.to,
+        };
+        hash ^= KEYS.pieces[piece_index(&capture, &color.opposite())][square.index()];
+    }
+    if mov.castling {
+        let (rook_from, rook_to) = get_castling_rook_squares(mov);
+        let rook = piece_index(&Piece::Rook, color);
+        hash ^= KEYS.pieces[rook][rook_from.index()] ^ KEYS.pieces[rook][rook_to.index()];
+    }
+    hash
+}
+
+pub fn hash_castling(castling: &CastlingAvailability) -> u64 {
+    let rights = [castling.white_kingside, castling.white_queenside, castling.black_kingside, castling.black_queenside];
+    rights
+        .iter()
+        .zip(KEYS.castling.iter())
+        .filter(|(right, _)| **right)
+        .fold(0, |hash, (_, key)| hash ^ key)
+}
+
+// only the file of the enpassant square is hashed
+pub fn hash_enpassant(enpassant: &Option<Square>) -> u64 {
+    match enpassant {
+        Some(square) => KEYS.enpassant[square.file().index() as usize],
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::{generate_bit_board, rules::get_possible_moves};
+
+    use super::*;
+
+    fn fen_hash(fen: &str) -> u64 {
+        let fen = generate_bit_board(fen).unwrap();
+        get_hash(&fen.board, &fen.color, &fen.castling, &fen.enpassant)
+    }
+
+    #[test]
+    fn test_hash_differs_by_state() {
+        let hash = fen_hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_ne!(hash, fen_hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"));
+        assert_ne!(hash, fen_hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1"));
+        assert_ne!(hash, fen_hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kkq e3 0 1"));
+        assert_eq!(hash, fen_hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 5 9"));
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+        ];
+        for fen in fens {
+            let fen = generate_bit_board(fen).unwrap();
+            let mut board = fen.board;
+            let hash = get_hash(&board, &fen.color, &fen.castling, &fen.enpassant);
+            for mov in get_possible_moves(&board, &fen.color, &fen.enpassant, &fen.castling) {
+                let castling = fen.castling.after_move(&mov, &fen.color);
+                let enpassant = mov.enpassant_square();
+                let updated = hash ^ hash_move(&mov, &fen.color)
+                    ^ hash_castling(&fen.castling) ^ hash_castling(&castling)
+                    ^ hash_enpassant(&fen.enpassant) ^ hash_enpassant(&enpassant);
+                board.apply_move(&mov, &fen.color);
+                assert_eq!(updated, get_hash(&board, &fen.color.opposite(), &castling, &enpassant));
+                board.undo_move(&mov, &fen.color);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+pub mod engine;
+
+use crate::engine::{rules::Piece, square::{Bitboard, File, Rank, Square}, BitBoard};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opposite(&self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    pub fn to_fen(&self) -> char {
+        match self {
+            Color::White => 'w',
+            Color::Black => 'b',
+        }
+    }
+}
+
+pub fn print_bitboard(bitboard: Bitboard) {
+    for rank in Rank::ALL.into_iter().rev() {
+        for file in File::ALL {
+            if bitboard.contains(Square::new(file, rank)) {
+                print!("1 ");
+            } else {
+                print!(". ");
+            }
+        }
+        println!();
+    }
+}
+
+pub fn print_board(bitboard: &BitBoard) {
+    for rank in Rank::ALL.into_iter().rev() {
+        for file in File::ALL {
+            let square = Square::new(file, rank);
+            match bitboard.piece_at(square) {
+                Some((Color::Black, Piece::Pawn)) => print!("♟ "),
+                Some((Color::Black, Piece::Bishop)) => print!("♝ "),
+                Some((Color::Black, Piece::Knight)) => print!("♞ "),
+                Some((Color::Black, Piece::Rook)) => print!("♜ "),
+                Some((Color::Black, Piece::Queen)) => print!("♛ "),
+                Some((Color::Black, Piece::King)) => print!("♚ "),
+                Some((Color::White, Piece::Pawn)) => print!("♙ "),
+                Some((Color::White, Piece::Bishop)) => print!("♗ "),
+                Some((Color::White, Piece::Knight)) => print!("♘ "),
+                Some((Color::White, Piece::Rook)) => print!("♖ "),
+                Some((Color::White, Piece::Queen)) => print!("♕ "),
+                Some((Color::White, Piece::King)) => print!("♔ "),
+                None => print!(". "),
+            }
+        }
+        println!();
+    }
+}
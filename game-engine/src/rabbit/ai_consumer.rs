@@ -1,58 +1,77 @@
-use lapin::{message::DeliveryResult, options::BasicAckOptions, Channel};
+use lapin::message::DeliveryResult;
+use log::{debug, error};
+use std::{str::FromStr, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
 use serde::{Deserialize, Serialize};
 
-use crate::{engine::{generate_bit_board, get_engine, rules::{is_game_drawn, is_game_won, move_to_string, Piece}, EngineType}, rabbit::DESTINATION_EXCHANGE, Color};
+use chess::{engine::{get_engine_with_options, position::Position, rules::{game_state_from_hashes, move_to_string, repetition_hashes}, EngineType, SearchLimits}, Color};
+use tokio::{sync::Semaphore, time::{timeout, timeout_at, Instant}};
+
+use crate::config::{AiConfig, EngineConfig};
+
+use super::{ai_pool::AiPool, delivery::{parse_message, EngineErrorEvent, ErrorCode, Publisher}, move_consumer::{get_result, EngineEvent}};
+
+// time a stopped search gets to return its best move before the request times out
+const CANCEL_GRACE: Duration = Duration::from_millis(200);
+
+
+// shared by the ai consumers of every connection, so the limits hold across reconnects
+#[derive(Clone)]
+pub struct AiRunner {
+    pool: AiPool,
+    jobs: Arc<Semaphore>,
+    engine: EngineConfig,
+}
 
-use super::move_consumer::EngineEvent;
+impl AiRunner {
+    pub fn new(ai: &AiConfig, engine: &EngineConfig) -> AiRunner {
+        AiRunner {
+            pool: AiPool::new(ai.workers),
+            jobs: Arc::new(Semaphore::new(ai.jobs.max(1))),
+            engine: *engine,
+        }
+    }
 
+    // the think time starts when the request comes in, so waiting for a job counts against it
+    async fn run(&self, message: AIEvent) -> Result<EngineEvent, EngineErrorEvent> {
+        let think_time = message.think_time.map(Duration::from_millis).unwrap_or(self.engine.think_time);
+        let deadline = Instant::now() + think_time;
+        let Ok(_job) = timeout_at(deadline, self.jobs.acquire()).await else {
+            let text = format!("No free job within {} ms", think_time.as_millis());
+            return Err(EngineErrorEvent::new(message.game_id, ErrorCode::Timeout, text));
+        };
+        run_search(&self.pool, message, self.engine, deadline.saturating_duration_since(Instant::now())).await
+    }
+}
 
-pub fn set_ai_delegate(consumer: lapin::Consumer, channel: Channel) {
+pub fn set_ai_delegate(consumer: lapin::Consumer, publisher: Publisher, runner: AiRunner) {
     consumer.set_delegate({
         move |delivery: DeliveryResult| {
-            println!("New ai move request");
-            let channel = channel.clone();
+            debug!("New ai move request");
+            let publisher = publisher.clone();
+            let runner = runner.clone();
             async move {
-                let channel = channel.clone();
                 let delivery = match delivery {
                     Ok(Some(delivery)) => delivery,
                     Ok(None) => return,
-                    Err(error) => {
-                        println!("Failed to consume queue message {}", error);
+                    Err(err) => {
+                        error!("Failed to consume queue message {}", err);
                         return;
                     }
                 };
 
-                let message = std::str::from_utf8(&delivery.data).unwrap();
-                let message: AIEvent = match serde_json::from_str(message) {
+                let message: AIEvent = match parse_message(&delivery.data) {
                     Ok(msg) => msg,
                     Err(err) => {
-                        println!("Failed to deserialize game event: {:?}", err);
+                        publisher.reject_malformed(delivery, err).await;
                         return;
                     }
                 };
-                println!("Received message: {:?}", &message);
-
-
-                let response = process_ai_event(message);
-                println!("Response: {:?}", &response);
-                let response = serde_json::to_string(&response).unwrap();
-
-                if let Err(err) = channel
-                    .basic_publish(
-                        DESTINATION_EXCHANGE,
-                        "engine",
-                        Default::default(),
-                        response.into_bytes().as_slice(),
-                        Default::default(),
-                        )
-                        .await {
-                            println!("Failed to publish message to destination exchange: {:?}", err);
-                        };
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
+                debug!("Received message: {:?}", &message);
+
+                let response = runner.run(message).await;
+                debug!("Response: {:?}", &response);
+                publisher.respond(delivery, response).await;
             }
         }
     }
@@ -64,44 +83,170 @@ pub fn set_ai_delegate(consumer: lapin::Consumer, channel: Channel) {
 struct AIEvent {
     game_id: usize,
     game_state: String,
-    #[serde(rename = "type")]
-    ai_type: String,
+    // engine from the config is used when the request doesn't name one
+    #[serde(rename = "type", default)]
+    ai_type: Option<String>,
     color: Color,
+    #[serde(default)]
+    previous_states: Vec<String>,
+    // in milliseconds
+    #[serde(default)]
+    think_time: Option<u64>,
 }
 
-fn process_ai_event(message: AIEvent) -> EngineEvent {
-    let fen = generate_bit_board(&message.game_state).unwrap(); // TODO
-    let mut board = fen.board; 
-    let mut engine = get_engine(EngineType::Random);
-    let mov = engine.get_move(&mut board, &message.color);
-    board.apply_move(&mov, &message.color);
-    let mov_string = move_to_string(&mut board, &mov, &message.color, false, false);
-
-    let won = is_game_won(&board, &message.color);
-    let drawn = !won && is_game_drawn(&board, &message.color);
-    let finished = won || drawn;
-
-    let board = board.to_fen();
-    let color = message.color.opposite().to_fen();
-    let castling = fen.castling.after_move(&mov, &message.color).to_fen();
-    let enpassant = "-"; // TODO
-    let halfmoves = match (mov.piece, mov.capture) {
-        (Piece::Pawn, _) => 0,
-        (_, Some(_)) => 0,
-        _ => fen.halfmoves + 1,
+// the search is stopped once the think time has passed since the request came in, including time spent waiting for a worker
+async fn run_search(ai_pool: &AiPool, message: AIEvent, engine: EngineConfig, think_time: Duration) -> Result<EngineEvent, EngineErrorEvent> {
+    let game_id = message.game_id;
+    let stop = Arc::new(AtomicBool::new(false));
+    let limits = SearchLimits { depth: Some(engine.depth), time: Some(think_time), stop: Some(stop.clone()), ..Default::default() };
+    let mut job = ai_pool.spawn(move || {
+        match limits.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+            true => None,
+            false => Some(process_ai_event(message, &engine, &limits)),
+        }
+    });
+
+    let result = match timeout(think_time, &mut job).await {
+        Ok(result) => result,
+        Err(_) => {
+            stop.store(true, Ordering::Relaxed);
+            timeout(CANCEL_GRACE, job).await.unwrap_or(Ok(None))
+        },
     };
-    let moves = match &message.color {
-        Color::White => fen.moves,
-        Color::Black => fen.moves + 1,
+    match result {
+        Ok(Some(response)) => response,
+        Ok(None) => Err(EngineErrorEvent::new(game_id, ErrorCode::Timeout, format!("No move within {} ms", think_time.as_millis()))),
+        Err(_) => Err(EngineErrorEvent::new(game_id, ErrorCode::SearchFailed, String::from("Search ended without a move"))),
+    }
+}
+
+fn process_ai_event(message: AIEvent, engine: &EngineConfig, limits: &SearchLimits) -> Result<EngineEvent, EngineErrorEvent> {
+    let error = |code, text: String| EngineErrorEvent::new(message.game_id, code, text);
+    let engine_type = match &message.ai_type {
+        Some(name) => EngineType::from_str(name).map_err(|err| error(ErrorCode::UnknownEngine, err))?,
+        None => engine.engine_type,
     };
-    let new_state = format!("{board} {color} {castling} {enpassant} {halfmoves} {moves}");
+    let mut position = Position::from_fen(&message.game_state).map_err(|err| error(ErrorCode::InvalidState, err.to_string()))?;
+    if position.color != message.color {
+        return Err(error(ErrorCode::WrongColor, format!("{:?} is not to move", message.color)));
+    }
+    let history = repetition_hashes(&message.previous_states)
+        .map_err(|err| error(ErrorCode::InvalidState, format!("Invalid previous state: {}", err)))?;
+    let limits = SearchLimits { halfmoves: position.halfmoves, history: history.clone(), ..limits.clone() };
+    let mut engine = get_engine_with_options(engine_type, engine.depth, engine.hash_size);
+    let Some(mov) = engine.search(&mut position.board, &message.color, &position.enpassant, &position.castling, &limits, &mut |_| {}) else {
+        return Err(error(ErrorCode::NoLegalMoves, String::from("No legal moves in this position")));
+    };
+    let mov_string = move_to_string(&position.board, &mov, &message.color);
+    position.make_move(&mov);
+    let state = game_state_from_hashes(&position, &history);
+    debug!("Game state: {:?}", state);
+    let result = get_result(&state, &message.color);
+
+    let new_state = position.to_fen();
 
-    EngineEvent {
+    Ok(EngineEvent {
         game_id: message.game_id,
         new_state,
         mov: mov_string,
         legal: true,
-        finished,
+        finished: result.is_some(),
         color: message.color,
+        result: result.map(|(result, _)| result),
+        reason: result.map(|(_, reason)| reason),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ai_event(game_state: &str, ai_type: &str, color: Color) -> AIEvent {
+        AIEvent {
+            game_id: 1,
+            game_state: game_state.to_string(),
+            ai_type: Some(ai_type.to_string()),
+            color,
+            previous_states: Vec::new(),
+            think_time: None,
+        }
+    }
+
+    fn engine_config(depth: u8, think_time: u64) -> EngineConfig {
+        EngineConfig { engine_type: EngineType::AlphaBeta, depth, think_time: Duration::from_millis(think_time), hash_size: 1 }
+    }
+
+    #[test]
+    fn test_ai_move() {
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Random", Color::White);
+        let event = process_ai_event(message, &engine_config(2, 1000), &SearchLimits::default()).unwrap();
+        assert!(event.legal);
+        assert!(!event.finished);
+    }
+
+    #[test]
+    fn test_default_engine() {
+        let mut message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Random", Color::White);
+        message.ai_type = None;
+        let engine = EngineConfig { engine_type: EngineType::Random, ..engine_config(2, 1000) };
+        assert!(process_ai_event(message, &engine, &SearchLimits::default()).unwrap().legal);
+    }
+
+    #[test]
+    fn test_ai_errors() {
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Minimax", Color::White);
+        assert_eq!(process_ai_event(message, &engine_config(2, 1000), &SearchLimits::default()).unwrap_err().error, ErrorCode::UnknownEngine);
+
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", "Random", Color::White);
+        assert_eq!(process_ai_event(message, &engine_config(2, 1000), &SearchLimits::default()).unwrap_err().error, ErrorCode::InvalidState);
+
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Random", Color::Black);
+        assert_eq!(process_ai_event(message, &engine_config(2, 1000), &SearchLimits::default()).unwrap_err().error, ErrorCode::WrongColor);
+
+        let message = ai_event("7k/8/6QK/8/8/8/8/8 b - - 0 1", "AlphaBeta", Color::Black);
+        assert_eq!(process_ai_event(message, &engine_config(2, 1000), &SearchLimits::default()).unwrap_err().error, ErrorCode::NoLegalMoves);
+    }
+
+    #[tokio::test]
+    async fn test_search_respects_think_time() {
+        let pool = AiPool::new(1);
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "AlphaBeta", Color::White);
+        // depth 64 is never reached, so a move means the search was stopped in time
+        let event = run_search(&pool, message, engine_config(64, 100), Duration::from_millis(100)).await.unwrap();
+        assert!(event.legal);
+    }
+
+    #[tokio::test]
+    async fn test_search_times_out_waiting_for_worker() {
+        let pool = AiPool::new(1);
+        let busy = pool.spawn(|| std::thread::sleep(Duration::from_millis(500)));
+        let message = ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "AlphaBeta", Color::White);
+        let error = run_search(&pool, message, engine_config(64, 50), Duration::from_millis(50)).await.unwrap_err();
+        assert_eq!(error.error, ErrorCode::Timeout);
+        busy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_runner_limits_jobs() {
+        let runner = AiRunner::new(&AiConfig { workers: 2, jobs: 1 }, &engine_config(2, 60_000));
+        let job = runner.jobs.acquire().await.unwrap();
+        let task = tokio::spawn({
+            let runner = runner.clone();
+            async move { runner.run(ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "AlphaBeta", Color::White)).await }
+        });
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!task.is_finished());
+        drop(job);
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_runner_times_out_waiting_for_job() {
+        let runner = AiRunner::new(&AiConfig { workers: 1, jobs: 1 }, &engine_config(64, 100));
+        let _job = runner.jobs.acquire().await.unwrap();
+        let error = runner.run(ai_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "AlphaBeta", Color::White)).await.unwrap_err();
+        assert_eq!(error.error, ErrorCode::Timeout);
     }
 }
@@ -0,0 +1,70 @@
+use std::{panic::{self, AssertUnwindSafe}, sync::{mpsc, Arc, Mutex}, thread};
+
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// searches run on their own threads, so they never block the runtime driving the consumers and heartbeats
+#[derive(Clone)]
+pub struct AiPool {
+    sender: mpsc::Sender<Job>,
+}
+
+impl AiPool {
+    pub fn new(workers: usize) -> AiPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("ai-worker-{}", i))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    // a panicking job drops its result sender, the worker itself keeps running
+                    match job {
+                        Ok(job) => {
+                            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                        },
+                        Err(_) => break,
+                    }
+                })
+                .expect("Cannot spawn ai worker");
+        }
+        AiPool { sender }
+    }
+
+    // the receiver yields an error if the job panicked or the pool is gone
+    pub fn spawn<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> oneshot::Receiver<T> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.sender.send(Box::new(move || {
+            let _ = sender.send(job());
+        }));
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jobs_run_on_workers() {
+        let pool = AiPool::new(2);
+        let jobs: Vec<_> = (0..4).map(|i| pool.spawn(move || (i, thread::current().name().map(String::from)))).collect();
+        for (i, job) in jobs.into_iter().enumerate() {
+            let (result, name) = job.await.unwrap();
+            assert_eq!(result, i);
+            assert!(name.unwrap().starts_with("ai-worker-"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_job() {
+        let pool = AiPool::new(1);
+        assert!(pool.spawn(|| panic!("search failed")).await.is_err());
+        assert_eq!(pool.spawn(|| 5).await.unwrap(), 5);
+    }
+}
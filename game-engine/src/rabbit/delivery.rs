@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use lapin::{message::Delivery, options::{BasicAckOptions, BasicRejectOptions}, Channel};
+use log::{error, info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::config::{BrokerConfig, RetryConfig};
+
+use super::topology::{get_retry_count, with_retry_count, with_retry_delay};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    MalformedMessage,
+    InvalidState,
+    WrongColor,
+    UnknownEngine,
+    NoLegalMoves,
+    Timeout,
+    SearchFailed,
+}
+
+impl ErrorCode {
+    // searches that ran out of time or crashed can succeed on another attempt, the other errors can't
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ErrorCode::Timeout | ErrorCode::SearchFailed)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineErrorEvent {
+    pub game_id: usize,
+    pub error: ErrorCode,
+    pub message: String,
+}
+
+impl EngineErrorEvent {
+    pub fn new(game_id: usize, error: ErrorCode, message: String) -> EngineErrorEvent {
+        EngineErrorEvent { game_id, error, message }
+    }
+}
+
+#[derive(Debug)]
+pub struct MalformedMessage {
+    pub game_id: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameId {
+    game_id: usize,
+}
+
+// game id is still looked up in messages that fail to deserialize, so the game can be told about the error
+pub fn parse_message<T: DeserializeOwned>(data: &[u8]) -> Result<T, MalformedMessage> {
+    let message = match std::str::from_utf8(data) {
+        Ok(message) => message,
+        Err(err) => return Err(MalformedMessage { game_id: None, message: format!("Message is not valid UTF-8: {}", err) }),
+    };
+    serde_json::from_str(message).map_err(|err| MalformedMessage {
+        game_id: serde_json::from_str::<GameId>(message).ok().map(|id| id.game_id),
+        message: format!("Failed to deserialize message: {}", err),
+    })
+}
+
+// sends the responses of one consumer on its channel, with the broker names and retry policy from the config
+#[derive(Clone)]
+pub struct Publisher {
+    channel: Channel,
+    broker: Arc<BrokerConfig>,
+    retry: RetryConfig,
+}
+
+impl Publisher {
+    pub fn new(channel: Channel, broker: Arc<BrokerConfig>, retry: RetryConfig) -> Publisher {
+        Publisher { channel, broker, retry }
+    }
+
+    // malformed deliveries won't get better on redelivery, so they are rejected without requeueing
+    pub async fn reject_malformed(&self, delivery: Delivery, error: MalformedMessage) {
+        warn!("Rejecting message: {}", error.message);
+        if let Some(game_id) = error.game_id {
+            let event = EngineErrorEvent::new(game_id, ErrorCode::MalformedMessage, error.message);
+            if let Err(err) = self.publish(&self.broker.error_routing_key, &event).await {
+                error!("Failed to publish error event: {}", err);
+            }
+        }
+        reject(delivery).await;
+    }
+
+    // delivery is acked once the response or the error event is published, and retried if publishing fails;
+    // transient processing errors are retried first and only reported to the game once the retries run out
+    pub async fn respond<T: Serialize + Sync>(&self, delivery: Delivery, response: Result<T, EngineErrorEvent>) {
+        let published = match &response {
+            Ok(event) => self.publish(&self.broker.response_routing_key, event).await,
+            Err(error) if error.error.is_transient() && get_retry_count(&delivery.properties) < self.retry.limit => {
+                warn!("Failed to process game {}, retrying: {}", error.game_id, error.message);
+                self.retry_or_reject(delivery).await;
+                return;
+            },
+            Err(error) => {
+                warn!("Failed to process game {}: {}", error.game_id, error.message);
+                self.publish(&self.broker.error_routing_key, error).await
+            },
+        };
+
+        match published {
+            Ok(_) => {
+                if let Err(err) = delivery.ack(BasicAckOptions::default()).await {
+                    error!("Failed to acknowledge message: {}", err);
+                }
+            },
+            Err(err) => {
+                error!("Failed to publish message to destination exchange: {}", err);
+                self.retry_or_reject(delivery).await;
+            },
+        }
+    }
+
+    // a copy of the message goes to the retry queue with an increased retry count and the original is acked;
+    // once the limit is reached the message is rejected to the dead-letter queue
+    async fn retry_or_reject(&self, delivery: Delivery) {
+        let count = get_retry_count(&delivery.properties);
+        if count >= self.retry.limit {
+            warn!("Message failed after {} retries, moving it to the dead-letter queue", count);
+            reject(delivery).await;
+            return;
+        }
+
+        let delay = self.retry.get_delay(count);
+        let properties = with_retry_delay(&with_retry_count(&delivery.properties, count + 1), delay);
+        let published = self.channel
+            .basic_publish(
+                &self.broker.retry_exchange,
+                delivery.routing_key.as_str(),
+                Default::default(),
+                &delivery.data,
+                properties,
+                )
+            .await;
+        match published {
+            Ok(_) => {
+                info!("Retrying message in {} ms (retry {} of {})", delay.as_millis(), count + 1, self.retry.limit);
+                if let Err(err) = delivery.ack(BasicAckOptions::default()).await {
+                    error!("Failed to acknowledge message: {}", err);
+                }
+            },
+            Err(err) => {
+                error!("Failed to publish message to retry exchange: {}", err);
+                reject(delivery).await;
+            },
+        }
+    }
+
+    async fn publish<T: Serialize + Sync>(&self, routing_key: &str, event: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let payload = serde_json::to_vec(event)?;
+        self.channel
+            .basic_publish(
+                &self.broker.destination_exchange,
+                routing_key,
+                Default::default(),
+                &payload,
+                Default::default(),
+                )
+            .await?;
+        Ok(())
+    }
+}
+
+async fn reject(delivery: Delivery) {
+    if let Err(err) = delivery.reject(BasicRejectOptions { requeue: false }).await {
+        error!("Failed to reject message: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Event {
+        game_id: usize,
+        game_state: String,
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let event: Event = parse_message(br#"{"gameId":5,"gameState":"8/8/8/8/8/8/8/8 w - - 0 1"}"#).unwrap();
+        assert_eq!(event.game_id, 5);
+        assert_eq!(event.game_state, "8/8/8/8/8/8/8/8 w - - 0 1");
+    }
+
+    #[test]
+    fn test_parse_malformed_message() {
+        let error = parse_message::<Event>(br#"{"gameId":5}"#).unwrap_err();
+        assert_eq!(error.game_id, Some(5));
+
+        let error = parse_message::<Event>(br#"{"gameState":"8/8/8/8/8/8/8/8 w - - 0 1"}"#).unwrap_err();
+        assert_eq!(error.game_id, None);
+
+        let error = parse_message::<Event>(b"\xff\xfe").unwrap_err();
+        assert_eq!(error.game_id, None);
+    }
+
+    #[test]
+    fn test_error_event_json() {
+        let event = EngineErrorEvent::new(5, ErrorCode::NoLegalMoves, String::from("No legal moves"));
+        let event = serde_json::to_value(event).unwrap();
+        assert_eq!(event["gameId"], 5);
+        assert_eq!(event["error"], "no_legal_moves");
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(ErrorCode::Timeout.is_transient());
+        assert!(ErrorCode::SearchFailed.is_transient());
+        assert!(!ErrorCode::InvalidState.is_transient());
+        assert!(!ErrorCode::MalformedMessage.is_transient());
+    }
+}
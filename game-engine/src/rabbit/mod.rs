@@ -1,115 +1,112 @@
-use std::time::Duration;
-use lapin::{options::{BasicConsumeOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions}, types::FieldTable, ExchangeKind};
+use std::{sync::Arc, time::Duration};
+use lapin::{options::{BasicConsumeOptions, BasicQosOptions}, types::FieldTable, Channel, Connection};
+use log::{debug, error, info};
 
-use self::{ai_consumer::set_ai_delegate, move_consumer::set_move_delegate};
+use crate::config::{BrokerConfig, ConfigFin, PrefetchConfig, RetryConfig};
+
+use self::{ai_consumer::{set_ai_delegate, AiRunner}, delivery::Publisher, move_consumer::set_move_delegate, topology::{declare_topology, retire_queue}};
 
 mod move_consumer;
 mod ai_consumer;
-
-const EXCHANGE_NAME: &str = "chess.moves.topic";
-const MOVES_QUEUE: &str = "chess.moves.queue";
-const AI_QUEUE: &str = "chess.moves.ai.queue";
-
-pub const DESTINATION_EXCHANGE: &str = "chess.engine.topic";
-
-pub async fn lapin_listen(pool: deadpool_lapin::Pool) {
-    let mut retry_interval = tokio::time::interval(Duration::from_secs(5));
+mod delivery;
+mod ai_pool;
+pub mod topology;
+
+pub async fn lapin_listen(pool: deadpool_lapin::Pool, config: &ConfigFin) {
+    let broker = Arc::new(config.broker.clone());
+    let ai_runner = AiRunner::new(&config.ai, &config.engine);
+    let mut retry_interval = tokio::time::interval(config.reconnect_interval);
     loop {
         retry_interval.tick().await;
-        println!("Connecting rmq consumer...");
-        match init_lapin_listen(pool.clone()).await {
-            Ok(_) => println!("RabbitMq listen returned"),
-            Err(e) => println!("RabbitMq listen had an error: {}", e),
+        info!("Connecting rmq consumer...");
+        match init_lapin_listen(pool.clone(), broker.clone(), config.retry, ai_runner.clone(), config.prefetch, config.reconnect_interval).await {
+            Ok(_) => info!("RabbitMq listen returned"),
+            Err(e) => error!("RabbitMq listen had an error: {}", e),
         };
     }
 }
 
-async fn init_lapin_listen(pool: deadpool_lapin::Pool) -> Result<(), Box<dyn std::error::Error>> {
+async fn init_lapin_listen(pool: deadpool_lapin::Pool, broker: Arc<BrokerConfig>, retry: RetryConfig, ai_runner: AiRunner, prefetch: PrefetchConfig, check_interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
     let rmq_con = pool.get().await
         .map_err(|e| {
-        println!("Could not get RabbitMQ connnection: {}", e);
+        error!("Could not get RabbitMQ connnection: {}", e);
         e
     })?;
-    let channel = rmq_con.create_channel().await?;
-
-    channel.queue_declare(
-        MOVES_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await
-        .expect("Cannot declare queue");
-
-    channel
-        .queue_bind(
-            MOVES_QUEUE,
-            EXCHANGE_NAME,
-            "move",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot bind queue");
-
-    channel.queue_declare(
-        AI_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await
-        .expect("Cannot declare queue");
-
-    channel
-        .queue_bind(
-            AI_QUEUE,
-            EXCHANGE_NAME,
-            "ai",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot bind queue");
+    // each consumer has its own channel and prefetch, so slow ai requests don't hold back move validation
+    let move_channel = rmq_con.create_channel().await?;
+    let ai_channel = match rmq_con.create_channel().await {
+        Ok(channel) => channel,
+        Err(e) => {
+            let _ = move_channel.close(200, "Reconnecting").await;
+            return Err(e.into());
+        }
+    };
+    // the old queues are only retired after the new ones are bound, so no message is left without a queue
+    let result = match declare_topology(&move_channel, &broker, &retry).await {
+        Ok(()) => {
+            retire_old_queues(&rmq_con, &broker).await;
+            consume(&move_channel, &ai_channel, broker, retry, ai_runner, prefetch, check_interval).await
+        },
+        Err(e) => Err(e),
+    };
+    // the pooled connection may still be open, so a consumer that did start is cancelled with its channel
+    let _ = move_channel.close(200, "Reconnecting").await;
+    let _ = ai_channel.close(200, "Reconnecting").await;
+
+    Ok(result?)
+}
 
-    channel
-        .exchange_declare(
-            DESTINATION_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot declare exchange");
+// returns once either channel is disconnected
+async fn consume(move_channel: &Channel, ai_channel: &Channel, broker: Arc<BrokerConfig>, retry: RetryConfig, ai_runner: AiRunner, prefetch: PrefetchConfig, check_interval: Duration) -> Result<(), lapin::Error> {
+    move_channel.basic_qos(prefetch.moves, BasicQosOptions::default()).await?;
+    ai_channel.basic_qos(prefetch.ai, BasicQosOptions::default()).await?;
 
-    let move_consumer = channel.basic_consume(
-        MOVES_QUEUE,
-        "engine_move_consumer",
+    let move_consumer = move_channel.basic_consume(
+        &broker.moves_queue,
+        &broker.move_consumer_tag,
         BasicConsumeOptions::default(),
         FieldTable::default())
-        .await
-        .expect("Cannot create consumer");
+        .await?;
 
-    let ai_consumer = channel.basic_consume(
-        AI_QUEUE,
-        "engine_ai_consumer",
+    let ai_consumer = ai_channel.basic_consume(
+        &broker.ai_queue,
+        &broker.ai_consumer_tag,
         BasicConsumeOptions::default(),
         FieldTable::default())
-        .await
-        .expect("Cannot create consumer");
+        .await?;
 
-    set_move_delegate(move_consumer, channel.clone());
-    set_ai_delegate(ai_consumer, channel.clone());
+    set_move_delegate(move_consumer, Publisher::new(move_channel.clone(), broker.clone(), retry));
+    set_ai_delegate(ai_consumer, Publisher::new(ai_channel.clone(), broker, retry), ai_runner);
 
-    let mut test_interval = tokio::time::interval(Duration::from_secs(5));
+    let mut test_interval = tokio::time::interval(check_interval);
     loop {
         test_interval.tick().await;
-        match channel.status().connected() {
-            false => break,
-            true => {},
+        if !(move_channel.status().connected() && ai_channel.status().connected()) {
+            break;
         }
     }
-
     Ok(())
 }
+
+// a missing queue closes the channel, so each old queue gets one of its own
+async fn retire_old_queues(connection: &Connection, broker: &BrokerConfig) {
+    let old_queues = [
+        (&broker.old_moves_queue, &broker.moves_routing_key),
+        (&broker.old_ai_queue, &broker.ai_routing_key),
+    ];
+    for (queue, routing_key) in old_queues {
+        let result = match connection.create_channel().await {
+            Ok(channel) => {
+                let result = retire_queue(&channel, queue, &broker.exchange, routing_key).await;
+                let _ = channel.close(200, "Queue retired").await;
+                result
+            },
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(moved) => info!("Moved {} messages from old queue {} and deleted it", moved, queue),
+            // usually the queue was already deleted
+            Err(err) => debug!("Old queue {} not retired: {}", queue, err),
+        }
+    }
+}
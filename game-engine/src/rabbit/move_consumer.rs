@@ -1,55 +1,38 @@
-use lapin::{message::DeliveryResult, options::BasicAckOptions, Channel};
+use lapin::message::DeliveryResult;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 
-use crate::{engine::{generate_bit_board, rules::{game_state, string_to_move, GameState, Piece}}, rabbit::DESTINATION_EXCHANGE, Color};
+use chess::{engine::{position::Position, rules::{game_state, string_to_move, GameState}}, Color};
 
-pub fn set_move_delegate(consumer: lapin::Consumer, channel: Channel) {
+use super::delivery::{parse_message, EngineErrorEvent, ErrorCode, Publisher};
+
+pub fn set_move_delegate(consumer: lapin::Consumer, publisher: Publisher) {
     consumer.set_delegate({
         move |delivery: DeliveryResult| {
-            println!("New move verification request");
-            let channel = channel.clone();
+            debug!("New move verification request");
+            let publisher = publisher.clone();
             async move {
-                let channel = channel.clone();
                 let delivery = match delivery {
                     Ok(Some(delivery)) => delivery,
                     Ok(None) => return,
-                    Err(error) => {
-                        println!("Failed to consume queue message {}", error);
+                    Err(err) => {
+                        error!("Failed to consume queue message {}", err);
                         return;
                     }
                 };
 
-                let message = std::str::from_utf8(&delivery.data).unwrap();
-                let message: MoveEvent = match serde_json::from_str(message) {
+                let message: MoveEvent = match parse_message(&delivery.data) {
                     Ok(msg) => msg,
                     Err(err) => {
-                        println!("Failed to deserialize game event: {:?}", err);
+                        publisher.reject_malformed(delivery, err).await;
                         return;
                     }
                 };
-                println!("Received message: {:?}", &message);
-
+                debug!("Received message: {:?}", &message);
 
                 let response = process_move(message);
-                println!("Response: {:?}", &response);
-                let response = serde_json::to_string(&response).unwrap();
-
-                if let Err(err) = channel
-                    .basic_publish(
-                        DESTINATION_EXCHANGE,
-                        "engine",
-                        Default::default(),
-                        response.into_bytes().as_slice(),
-                        Default::default(),
-                        )
-                        .await {
-                            println!("Failed to publish message to destination exchange: {:?}", err);
-                        };
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
+                debug!("Response: {:?}", &response);
+                publisher.respond(delivery, response).await;
             }
         }
     }
@@ -64,6 +47,8 @@ struct MoveEvent {
     #[serde(rename = "move")]
     mov: String,
     color: Color,
+    #[serde(default)]
+    previous_states: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,60 +61,163 @@ pub struct EngineEvent {
     pub mov: String,
     pub finished: bool,
     pub color: Color,
+    pub result: Option<GameResult>,
+    pub reason: Option<GameReason>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameReason {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FivefoldRepetition,
+    FiftyMoves,
+    SeventyFiveMoves,
+    InsufficientMaterial,
+    DeadPosition,
+}
+
+// color is the side that made the move
+pub fn get_result(state: &GameState, color: &Color) -> Option<(GameResult, GameReason)> {
+    let reason = match state {
+        GameState::Normal | GameState::Check => return None,
+        GameState::Checkmate => {
+            let result = match color {
+                Color::White => GameResult::WhiteWins,
+                Color::Black => GameResult::BlackWins,
+            };
+            return Some((result, GameReason::Checkmate));
+        },
+        GameState::Stalemate => GameReason::Stalemate,
+        GameState::FiftyMoves => GameReason::FiftyMoves,
+        GameState::SeventyFiveMoves => GameReason::SeventyFiveMoves,
+        GameState::ThreefoldRepetition => GameReason::Repetition,
+        GameState::FivefoldRepetition => GameReason::FivefoldRepetition,
+        GameState::InsufficientMaterial => GameReason::InsufficientMaterial,
+        GameState::DeadPosition => GameReason::DeadPosition,
+    };
+    Some((GameResult::Draw, reason))
 }
 
-fn process_move(message: MoveEvent) -> EngineEvent {
-    let fen = generate_bit_board(&message.game_state).unwrap();
-    let mut board = fen.board; // TODO
-    if fen.color != message.color {
-        return EngineEvent {
+fn process_move(message: MoveEvent) -> Result<EngineEvent, EngineErrorEvent> {
+    let mut position = match Position::from_fen(&message.game_state) {
+        Ok(position) => position,
+        Err(err) => return Err(EngineErrorEvent::new(message.game_id, ErrorCode::InvalidState, err.to_string())),
+    };
+    if position.color != message.color {
+        return Ok(EngineEvent {
             game_id: message.game_id,
             new_state: message.game_state,
             mov: message.mov,
             legal: false,
             finished: false,
             color: message.color,
-        }
+            result: None,
+            reason: None,
+        })
     }
-    let mov = string_to_move(&mut board, message.mov.clone(), &message.color);
+    let mov = string_to_move(&position.board, &message.mov, &message.color, &position.enpassant, &position.castling);
     let legal = mov.is_ok();
-    let (new_state, finished) = match mov {
+    let (new_state, result) = match mov {
         Ok(mov) => {
-            board.apply_move(&mov, &message.color);
-            let state = game_state(&mut board, &message.color);
-            let won = match state {
-                GameState::Checkmate => true,
-                _ => false,
-            };
-            let drawn = match state {
-                GameState::Stalemate => true,
-                _ => false,
-            };
-            let board = board.to_fen();
-            let color = message.color.opposite().to_fen();
-            let castling = fen.castling.after_move(&mov, &message.color).to_fen();
-            let enpassant = "-"; // TODO
-            let halfmoves = match (mov.piece, mov.capture) {
-                (Piece::Pawn, _) => 0,
-                (_, Some(_)) => 0,
-                _ => fen.halfmoves + 1,
-            };
-            let moves = match &message.color {
-                Color::White => fen.moves,
-                Color::Black => fen.moves + 1,
-            };
-            let new_state = format!("{board} {color} {castling} {enpassant} {halfmoves} {moves}");
-            (new_state, won || drawn)
+            position.make_move(&mov);
+            let state = game_state(&position, &message.previous_states)
+                .map_err(|err| EngineErrorEvent::new(message.game_id, ErrorCode::InvalidState, format!("Invalid previous state: {}", err)))?;
+            debug!("Game state: {:?}", state);
+            (position.to_fen(), get_result(&state, &message.color))
         },
-        Err(_) => (message.game_state, false),
+        Err(_) => (message.game_state, None),
     };
 
-    EngineEvent {
+    Ok(EngineEvent {
         game_id: message.game_id,
         new_state,
         mov: message.mov,
         legal,
-        finished,
+        finished: result.is_some(),
         color: message.color,
+        result: result.map(|(result, _)| result),
+        reason: result.map(|(_, reason)| reason),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_event(game_state: &str, mov: &str, color: Color) -> MoveEvent {
+        MoveEvent {
+            game_id: 1,
+            game_state: game_state.to_string(),
+            mov: mov.to_string(),
+            color,
+            previous_states: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_checkmate_result() {
+        let message = move_event("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "Qh4", Color::Black);
+        let event = process_move(message).unwrap();
+        assert!(event.finished);
+        assert_eq!(event.result, Some(GameResult::BlackWins));
+        assert_eq!(event.reason, Some(GameReason::Checkmate));
+    }
+
+    #[test]
+    fn test_stalemate_result() {
+        let message = move_event("7k/8/5K2/6Q1/8/8/8/8 w - - 0 1", "Qg6", Color::White);
+        let event = process_move(message).unwrap();
+        assert!(event.finished);
+        assert_eq!(event.result, Some(GameResult::Draw));
+        assert_eq!(event.reason, Some(GameReason::Stalemate));
+    }
+
+    #[test]
+    fn test_draw_reasons() {
+        let message = move_event("4k3/8/8/8/8/8/8/R3K3 w - - 149 90", "Ra2", Color::White);
+        let event = serde_json::to_value(process_move(message).unwrap()).unwrap();
+        assert_eq!(event["reason"], "seventy_five_moves");
+
+        let mut message = move_event("4k3/8/8/8/8/8/8/R3K3 w - - 20 40", "Ra2", Color::White);
+        message.previous_states = vec!["4k3/8/8/8/8/8/R7/4K3 b - - 12 36".to_string(); 4];
+        let event = serde_json::to_value(process_move(message).unwrap()).unwrap();
+        assert_eq!(event["reason"], "fivefold_repetition");
+    }
+
+    #[test]
+    fn test_event_json() {
+        let message = move_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e4", Color::White);
+        let event = serde_json::to_value(process_move(message).unwrap()).unwrap();
+        assert_eq!(event["finished"], false);
+        assert!(event["result"].is_null());
+        assert!(event["reason"].is_null());
+
+        let message = move_event("4k3/8/8/8/8/8/8/3bK3 w - - 0 1", "Kxd1", Color::White);
+        let event = serde_json::to_value(process_move(message).unwrap()).unwrap();
+        assert_eq!(event["finished"], true);
+        assert_eq!(event["result"], "draw");
+        assert_eq!(event["reason"], "insufficient_material");
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let message = move_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - 0 1", "e4", Color::White);
+        let error = process_move(message).unwrap_err();
+        assert_eq!(error.game_id, 1);
+        assert_eq!(error.error, ErrorCode::InvalidState);
+
+        let mut message = move_event("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e4", Color::White);
+        message.previous_states = vec!["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1".to_string()];
+        assert_eq!(process_move(message).unwrap_err().error, ErrorCode::InvalidState);
     }
 }
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use lapin::{options::{BasicAckOptions, BasicGetOptions, BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions, QueueDeleteOptions}, types::{AMQPValue, FieldTable}, BasicProperties, Channel, ExchangeKind};
+
+use crate::config::{BrokerConfig, RetryConfig};
+
+pub const RETRY_COUNT_HEADER: &str = "x-retry-count";
+// headers exchanges ignore binding arguments starting with x-, so this one has no prefix
+pub const RETRY_DELAY_HEADER: &str = "retry-delay-ms";
+
+// rejected messages are dead-lettered with their original routing key.
+// retried messages are routed by their delay header to the retry queue for that backoff step,
+// where they wait for the queue's ttl and go back to the moves exchange; every message in a queue
+// has the same ttl, so a long delay never holds back a shorter one.
+// the moves and ai queues are declared under new names, as the broker refuses to redeclare
+// the old ones as durable or with a dead-letter exchange; see retire_queue for the old ones
+pub async fn declare_topology(channel: &Channel, broker: &BrokerConfig, retry: &RetryConfig) -> Result<(), lapin::Error> {
+    let durable = ExchangeDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    let durable_queue = QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    channel.exchange_declare(&broker.dead_letter_exchange, ExchangeKind::Topic, durable, FieldTable::default()).await?;
+    channel.exchange_declare(&broker.retry_exchange, ExchangeKind::Headers, durable, FieldTable::default()).await?;
+    channel.exchange_declare(&broker.destination_exchange, ExchangeKind::Topic, durable, FieldTable::default()).await?;
+
+    channel.queue_declare(&broker.dead_letter_queue, durable_queue, FieldTable::default()).await?;
+    channel.queue_bind(&broker.dead_letter_queue, &broker.dead_letter_exchange, "#", QueueBindOptions::default(), FieldTable::default()).await?;
+
+    for delay in get_retry_delays(retry) {
+        let queue = get_retry_queue(broker, delay);
+        let mut arguments = dead_letter_to(&broker.exchange);
+        arguments.insert("x-message-ttl".into(), AMQPValue::LongLongInt(delay.as_millis() as i64));
+        channel.queue_declare(&queue, durable_queue, arguments).await?;
+
+        let mut binding = FieldTable::default();
+        binding.insert("x-match".into(), AMQPValue::LongString("all".into()));
+        binding.insert(RETRY_DELAY_HEADER.into(), delay_header(delay));
+        channel.queue_bind(&queue, &broker.retry_exchange, "", QueueBindOptions::default(), binding).await?;
+    }
+
+    channel.queue_declare(&broker.moves_queue, durable_queue, dead_letter_to(&broker.dead_letter_exchange)).await?;
+    channel.queue_bind(&broker.moves_queue, &broker.exchange, &broker.moves_routing_key, QueueBindOptions::default(), FieldTable::default()).await?;
+
+    channel.queue_declare(&broker.ai_queue, durable_queue, dead_letter_to(&broker.dead_letter_exchange)).await?;
+    channel.queue_bind(&broker.ai_queue, &broker.exchange, &broker.ai_routing_key, QueueBindOptions::default(), FieldTable::default()).await?;
+
+    Ok(())
+}
+
+// unbinds a queue from before the rename, sends the messages left in it through the exchange again,
+// so they end up in the queue that replaced it, and deletes it; returns the number of messages moved.
+// the broker closes the channel when the queue doesn't exist, so it needs a channel of its own
+pub async fn retire_queue(channel: &Channel, queue: &str, exchange: &str, routing_key: &str) -> Result<u32, lapin::Error> {
+    let passive = QueueDeclareOptions {
+        passive: true,
+        ..Default::default()
+    };
+    channel.queue_declare(queue, passive, FieldTable::default()).await?;
+    channel.queue_unbind(queue, exchange, routing_key, FieldTable::default()).await?;
+
+    let mut moved = 0;
+    while let Some(message) = channel.basic_get(queue, BasicGetOptions::default()).await? {
+        let delivery = message.delivery;
+        channel.basic_publish(exchange, delivery.routing_key.as_str(), BasicPublishOptions::default(), &delivery.data, delivery.properties.clone())
+            .await?
+            .await?;
+        delivery.ack(BasicAckOptions::default()).await?;
+        moved += 1;
+    }
+    // an engine that wasn't updated yet may still use the queue, it is deleted on a later start
+    channel.queue_delete(queue, QueueDeleteOptions { if_empty: true, if_unused: true, ..Default::default() }).await?;
+    Ok(moved)
+}
+
+fn dead_letter_to(exchange: &str) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString(exchange.into()));
+    arguments
+}
+
+// delays are capped, so high retry limits can share the last queues
+fn get_retry_delays(retry: &RetryConfig) -> Vec<Duration> {
+    let mut delays: Vec<Duration> = (0..retry.limit).map(|count| retry.get_delay(count)).collect();
+    delays.dedup();
+    delays
+}
+
+fn get_retry_queue(broker: &BrokerConfig, delay: Duration) -> String {
+    format!("{}.{}ms", broker.retry_queue, delay.as_millis())
+}
+
+fn delay_header(delay: Duration) -> AMQPValue {
+    AMQPValue::LongLongInt(delay.as_millis() as i64)
+}
+
+pub fn get_retry_count(properties: &BasicProperties) -> u32 {
+    properties.headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(RETRY_COUNT_HEADER))
+        .and_then(|count| count.as_long_uint())
+        .unwrap_or(0)
+}
+
+pub fn with_retry_count(properties: &BasicProperties, count: u32) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(RETRY_COUNT_HEADER.into(), AMQPValue::LongUInt(count));
+    properties.clone().with_headers(headers)
+}
+
+// the delay header picks the retry queue the message waits in
+pub fn with_retry_delay(properties: &BasicProperties, delay: Duration) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(RETRY_DELAY_HEADER.into(), delay_header(delay));
+    properties.clone().with_headers(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_count() {
+        let properties = BasicProperties::default();
+        assert_eq!(get_retry_count(&properties), 0);
+
+        let properties = with_retry_count(&properties, 1);
+        assert_eq!(get_retry_count(&properties), 1);
+        let properties = with_retry_count(&properties, 2);
+        assert_eq!(get_retry_count(&properties), 2);
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        let retry = RetryConfig { limit: 3, backoff: Duration::from_millis(500) };
+        let delays = get_retry_delays(&retry);
+        assert_eq!(delays, vec![Duration::from_millis(500), Duration::from_millis(1000), Duration::from_millis(2000)]);
+
+        let retry = RetryConfig { limit: 20, backoff: Duration::from_millis(1) };
+        assert_eq!(get_retry_delays(&retry).len(), 17);
+        assert!(get_retry_delays(&RetryConfig { limit: 0, ..retry }).is_empty());
+    }
+
+    #[test]
+    fn test_retry_delay_header() {
+        let properties = with_retry_delay(&with_retry_count(&BasicProperties::default(), 2), Duration::from_millis(2000));
+        let headers = properties.headers().clone().unwrap();
+        assert_eq!(headers.inner().get(RETRY_DELAY_HEADER), Some(&AMQPValue::LongLongInt(2000)));
+        assert_eq!(get_retry_count(&properties), 2);
+    }
+}
@@ -24,6 +24,7 @@ public class MovePublisher {
         event.setMove(move);
         event.setNonCapturingMoves(game.getNonCaptureMoves());
         event.setColor(game.getColor());
+        event.setPreviousStates(game.getPreviousStates());
         template.convertAndSend(movesTopic, "move", event);
     }
 
@@ -34,6 +35,7 @@ public class MovePublisher {
         event.setType(game.getAiType());
         event.setNonCapturingMoves(game.getNonCaptureMoves());
         event.setColor(game.getColor());
+        event.setPreviousStates(game.getPreviousStates());
         template.convertAndSend(movesTopic, "ai", event);
     }
 }
\ No newline at end of file
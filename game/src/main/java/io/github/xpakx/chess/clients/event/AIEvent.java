@@ -6,6 +6,8 @@ import io.github.xpakx.chess.game.dto.Color;
 import lombok.Getter;
 import lombok.Setter;
 
+import java.util.List;
+
 @Getter
 @Setter
 public class AIEvent {
@@ -14,4 +16,5 @@ public class AIEvent {
     private Integer nonCapturingMoves;
     private AIType type;
     private Color color;
+    private List<String> previousStates;
 }
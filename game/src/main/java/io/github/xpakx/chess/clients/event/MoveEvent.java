@@ -4,6 +4,8 @@ import io.github.xpakx.chess.game.dto.Color;
 import lombok.Getter;
 import lombok.Setter;
 
+import java.util.List;
+
 @Getter
 @Setter
 public class MoveEvent {
@@ -12,4 +14,5 @@ public class MoveEvent {
     private String move;
     private Integer nonCapturingMoves;
     private Color color;
+    private List<String> previousStates;
 }
@@ -1,5 +1,5 @@
 package io.github.xpakx.chess.game;
 
 public enum AIType {
-    Random, None;
+    Random, None, AlphaBeta;
 }
@@ -1,6 +1,7 @@
 package io.github.xpakx.chess.game;
 
 import io.github.xpakx.chess.clients.MovePublisher;
+import io.github.xpakx.chess.game.dto.EngineErrorEvent;
 import io.github.xpakx.chess.game.dto.EngineMoveEvent;
 import lombok.RequiredArgsConstructor;
 import org.springframework.amqp.AmqpRejectAndDontRequeueException;
@@ -20,4 +21,13 @@ public class EngineEventHandler {
             throw new AmqpRejectAndDontRequeueException(e);
         }
     }
+
+    @RabbitListener(queues = "${amqp.queue.engine-error}")
+    void handleError(final EngineErrorEvent event) {
+        try {
+            service.handleEngineError(event);
+        } catch (final Exception e) {
+            throw new AmqpRejectAndDontRequeueException(e);
+        }
+    }
 }
@@ -116,6 +116,26 @@ public class GameService {
         }
     }
 
+    public void handleEngineError(EngineErrorEvent event) {
+        var gameOpt = getGameById(event.getGameId());
+        if (gameOpt.isEmpty()) {
+            return;
+        }
+        var game = gameOpt.get();
+        logger.debug("Engine error {} in game {}: {}", event.getError(), game.getId(), event.getMessage());
+        game.setBlocked(false);
+        repository.save(game);
+        simpMessagingTemplate.convertAndSend(
+                "/topic/game/" + game.getId(),
+                MoveMessage.rejected(
+                        null,
+                        game.getCurrentPlayer(),
+                        game.getColor(),
+                        "Engine error: " + event.getMessage()
+                )
+        );
+    }
+
     public void doMakeMove(EngineMoveEvent event) {
         var game = getGameById(event.getGameId()).orElseThrow();
         if (!event.isLegal()) {
@@ -133,19 +153,17 @@ public class GameService {
             return;
         }
 
+        game.addPreviousState(game.getCurrentState());
         game.setCurrentState(event.getNewState());
         if (event.isFinished()) {
             game.setFinished(true);
-            if (game.isFirstUserTurn()) {
-                game.setWon(true);
-            } else {
-                game.setLost(true);
-            }
+            setResult(game, event.getResult());
         }
         var msg = MoveMessage.of(event.getMove(), game.getCurrentPlayer(), event.getColor());
         if (game.isFinished()) {
             msg.setFinished(true);
             msg.setWon(game.isWon());
+            msg.setDrawn(game.isDrawn());
             msg.setWinner(game.getWinner().orElse(null));
             repository.deleteById(game.getId());
         } else {
@@ -160,4 +178,29 @@ public class GameService {
             movePublisher.sendAIEvent(game);
         }
     }
+
+    private void setResult(GameState game, GameResult result) {
+        if (result == null) {
+            // older engines only finish games on checkmate
+            if (game.isFirstUserTurn()) {
+                game.setWon(true);
+            } else {
+                game.setLost(true);
+            }
+            return;
+        }
+        switch (result) {
+            case Draw -> game.setDrawn(true);
+            case WhiteWins -> setWinner(game, Color.White);
+            case BlackWins -> setWinner(game, Color.Black);
+        }
+    }
+
+    private void setWinner(GameState game, Color winner) {
+        if (game.getFirstUserColor() == winner) {
+            game.setWon(true);
+        } else {
+            game.setLost(true);
+        }
+    }
 }
@@ -6,6 +6,8 @@ import lombok.Setter;
 import org.springframework.data.redis.core.RedisHash;
 
 import java.io.Serializable;
+import java.util.ArrayList;
+import java.util.List;
 import java.util.Optional;
 
 @Getter
@@ -19,6 +21,7 @@ public class GameState implements Serializable {
     private boolean lost;
 
     private String currentState;
+    private List<String> previousStates = new ArrayList<>();
 
     private String username1;
     private String username2;
@@ -40,6 +43,13 @@ public class GameState implements Serializable {
                 (!user2AI && username.equals(username2));
     }
 
+    public void addPreviousState(String state) {
+        if (previousStates == null) {
+            previousStates = new ArrayList<>();
+        }
+        previousStates.add(state);
+    }
+
     public void nextPlayer() {
         firstUserTurn = !firstUserTurn;
     }
@@ -63,6 +73,10 @@ public class GameState implements Serializable {
         return Optional.of(winner != null ? winner : "AI");
     }
 
+    public Color getFirstUserColor() {
+        return firstUserStarts ? Color.White : Color.Black;
+    }
+
     public Color getColor() {
         if(firstUserStarts) {
             return firstUserTurn ? Color.White : Color.Black;
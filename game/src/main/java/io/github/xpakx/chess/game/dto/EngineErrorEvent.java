@@ -0,0 +1,12 @@
+package io.github.xpakx.chess.game.dto;
+
+import lombok.Getter;
+import lombok.Setter;
+
+@Getter
+@Setter
+public class EngineErrorEvent {
+    Long gameId;
+    String error;
+    String message;
+}
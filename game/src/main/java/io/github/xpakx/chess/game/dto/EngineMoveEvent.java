@@ -12,4 +12,6 @@ public class EngineMoveEvent {
     boolean finished;
     String newState;
     Color color;
+    GameResult result;
+    String reason;
 }
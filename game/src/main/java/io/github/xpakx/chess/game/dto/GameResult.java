@@ -0,0 +1,9 @@
+package io.github.xpakx.chess.game.dto;
+
+import com.fasterxml.jackson.annotation.JsonProperty;
+
+public enum GameResult {
+    @JsonProperty("white_wins") WhiteWins,
+    @JsonProperty("black_wins") BlackWins,
+    @JsonProperty("draw") Draw;
+}
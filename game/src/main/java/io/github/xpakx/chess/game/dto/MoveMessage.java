@@ -20,6 +20,7 @@ public class MoveMessage {
 
     private boolean finished;
     private boolean won;
+    private boolean drawn;
 
     @JsonInclude(JsonInclude.Include.NON_NULL)
     private String winner;
@@ -33,6 +34,7 @@ public class MoveMessage {
                 null,
                 false,
                 false,
+                false,
                 null,
                 color
         );
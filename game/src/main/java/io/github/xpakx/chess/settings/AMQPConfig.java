@@ -74,6 +74,18 @@ public class AMQPConfig {
                 .with("engine");
     }
 
+    @Bean
+    public Queue engineErrorQueue(@Value("${amqp.queue.engine-error}") final String queueName) {
+        return QueueBuilder.durable(queueName).build();
+    }
+
+    @Bean
+    public Binding engineErrorBinding(final Queue engineErrorQueue, final TopicExchange engineTopicExchange) {
+        return BindingBuilder.bind(engineErrorQueue)
+                .to(engineTopicExchange)
+                .with("engine.error");
+    }
+
     @Bean
     public MessageHandlerMethodFactory messageHandlerMethodFactory() {
         DefaultMessageHandlerMethodFactory factory = new DefaultMessageHandlerMethodFactory();
@@ -0,0 +1,100 @@
+package io.github.xpakx.chess.game;
+
+import io.github.xpakx.chess.clients.GamePublisher;
+import io.github.xpakx.chess.clients.MovePublisher;
+import io.github.xpakx.chess.clients.StatePublisher;
+import io.github.xpakx.chess.game.dto.Color;
+import io.github.xpakx.chess.game.dto.EngineMoveEvent;
+import io.github.xpakx.chess.game.dto.GameResult;
+import io.github.xpakx.chess.game.dto.MoveMessage;
+import org.junit.jupiter.api.BeforeEach;
+import org.junit.jupiter.api.Test;
+import org.mockito.ArgumentCaptor;
+import org.springframework.messaging.simp.SimpMessagingTemplate;
+
+import java.util.Optional;
+
+import static org.assertj.core.api.Assertions.assertThat;
+import static org.mockito.ArgumentMatchers.any;
+import static org.mockito.ArgumentMatchers.eq;
+import static org.mockito.Mockito.*;
+
+class GameServiceTest {
+    private SimpMessagingTemplate simpMessagingTemplate;
+    private GameRepository repository;
+    private GameService service;
+    private GameState game;
+
+    @BeforeEach
+    void setUp() {
+        simpMessagingTemplate = mock(SimpMessagingTemplate.class);
+        repository = mock(GameRepository.class);
+        service = new GameService(
+                simpMessagingTemplate,
+                repository,
+                mock(MovePublisher.class),
+                mock(GamePublisher.class),
+                mock(StatePublisher.class)
+        );
+        game = new GameState();
+        game.setId(1L);
+        game.setUsername1("user1");
+        game.setUsername2("user2");
+        game.setFirstUserStarts(true);
+        game.setFirstUserTurn(true);
+        game.setCurrentState("8/8/8/8/8/8/8/K6k w - - 0 1");
+        when(repository.findById(1L)).thenReturn(Optional.of(game));
+    }
+
+    private EngineMoveEvent finishedEvent(GameResult result, String reason) {
+        var event = new EngineMoveEvent();
+        event.setGameId(1L);
+        event.setLegal(true);
+        event.setMove("a1a2");
+        event.setFinished(true);
+        event.setNewState("8/8/8/8/8/8/K7/7k b - - 1 1");
+        event.setColor(Color.White);
+        event.setResult(result);
+        event.setReason(reason);
+        return event;
+    }
+
+    @Test
+    void shouldSaveDrawAsDrawn() {
+        service.doMakeMove(finishedEvent(GameResult.Draw, "insufficient_material"));
+
+        assertThat(game.isFinished()).isTrue();
+        assertThat(game.isDrawn()).isTrue();
+        assertThat(game.isWon()).isFalse();
+        assertThat(game.isLost()).isFalse();
+
+        var captor = ArgumentCaptor.forClass(MoveMessage.class);
+        verify(simpMessagingTemplate).convertAndSend(eq("/topic/game/1"), captor.capture());
+        assertThat(captor.getValue().isFinished()).isTrue();
+        assertThat(captor.getValue().isDrawn()).isTrue();
+        assertThat(captor.getValue().isWon()).isFalse();
+        assertThat(captor.getValue().getWinner()).isNull();
+        verify(repository).deleteById(1L);
+        verify(repository, never()).save(any());
+    }
+
+    @Test
+    void shouldSaveWinForFirstUser() {
+        service.doMakeMove(finishedEvent(GameResult.WhiteWins, "checkmate"));
+
+        assertThat(game.isWon()).isTrue();
+        assertThat(game.isLost()).isFalse();
+        assertThat(game.isDrawn()).isFalse();
+    }
+
+    @Test
+    void shouldSaveLossForFirstUser() {
+        game.setFirstUserStarts(false);
+        game.setFirstUserTurn(false);
+        service.doMakeMove(finishedEvent(GameResult.WhiteWins, "checkmate"));
+
+        assertThat(game.isLost()).isTrue();
+        assertThat(game.isWon()).isFalse();
+        assertThat(game.isDrawn()).isFalse();
+    }
+}